@@ -0,0 +1,155 @@
+//! Async wrapper over `LFU` moving eviction off the insert path, enabled with `tokio` feature
+
+use crate::LFU;
+use bytes::Bytes;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::{mpsc, oneshot};
+
+enum Command {
+    Insert(String, Bytes),
+    Flush(oneshot::Sender<()>),
+}
+
+/// `LFU` fed through a bounded channel. `insert` only enqueues the value, a background task
+/// drains the queue and does the actual insert, including any eviction it triggers. `get`, `peek`
+/// and the other reads are synchronous under a short lock and never wait for the queue.
+///
+/// Queued inserts are not visible yet, so `current_size`, `len` and reads are eventually
+/// consistent: right after `insert` returns the cache may neither hold the new value nor have
+/// evicted for it. `flush` waits until everything queued before it has been applied, after which
+/// the cache is within its `max_size` as usual.
+///
+/// When the queue is full `insert` waits for room, which bounds memory held by pending values.
+/// The background task runs until every handle is dropped.
+///
+/// ```
+/// use lfu_vecs::{AsyncLfu, LFU};
+/// use bytes::Bytes;
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let cache = AsyncLfu::new(LFU::new().max_size(1024), 64);
+/// cache.insert("a".to_string(), Bytes::from("b")).await;
+/// cache.flush().await;
+/// assert_eq!(cache.get("a"), Some(Bytes::from("b")));
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncLfu {
+    inner: Arc<Mutex<LFU>>,
+    queue: mpsc::Sender<Command>,
+}
+
+impl AsyncLfu {
+    ///
+    /// Wrap a cache queueing up to `capacity` inserts. Spawns the background task, so it has to be
+    /// called within a tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// When called outside of a tokio runtime or with zero `capacity`.
+    ///
+    pub fn new(lfu: LFU, capacity: usize) -> Self {
+        let inner = Arc::new(Mutex::new(lfu));
+        let (queue, commands) = mpsc::channel(capacity);
+        tokio::spawn(apply(Arc::clone(&inner), commands, capacity));
+        AsyncLfu { inner, queue }
+    }
+    ///
+    /// Queue a value for insertion, waiting only when the queue is full. Eviction happens later
+    /// in the background task.
+    ///
+    pub async fn insert(&self, key: String, value: Bytes) {
+        // receiver lives as long as any sender, so this can't fail
+        let _ = self.queue.send(Command::Insert(key, value)).await;
+    }
+    ///
+    /// Wait until every insert queued before this call has been applied
+    ///
+    pub async fn flush(&self) {
+        let (done, applied) = oneshot::channel();
+        if self.queue.send(Command::Flush(done)).await.is_ok() {
+            let _ = applied.await;
+        }
+    }
+    ///
+    /// Get a value bumping its frequency. Values still in the queue are not seen.
+    ///
+    pub fn get(&self, key: &str) -> Option<Bytes> {
+        self.lock().get(key)
+    }
+    ///
+    /// Read a value without bumping its frequency. Values still in the queue are not seen.
+    ///
+    pub fn peek(&self, key: &str) -> Option<Bytes> {
+        self.lock().peek(key)
+    }
+    ///
+    /// Check if we have value for this key, not counting queued inserts
+    ///
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.lock().contains_key(key)
+    }
+    ///
+    /// Current size of cached data. Eventually consistent, queued inserts and the evictions they
+    /// will cause are not reflected until applied, see `flush`.
+    ///
+    pub fn current_size(&self) -> usize {
+        self.lock().current_size()
+    }
+    ///
+    /// Number of entries in cache, not counting queued inserts
+    ///
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+    ///
+    /// Check if there are no entries in cache, not counting queued inserts
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+    ///
+    /// Lock over the whole cache for operations not mirrored by the wrapper. Don't hold it across
+    /// an `.await`, the background task blocks on it.
+    ///
+    /// # Panics
+    ///
+    /// When a thread panicked while holding the lock.
+    ///
+    pub fn lock(&self) -> MutexGuard<'_, LFU> {
+        self.inner.lock().expect("AsyncLfu lock poisoned")
+    }
+}
+
+/// Background task applying queued commands, batching whatever is already queued under one lock.
+/// A batch takes at most `batch` commands so that readers get the lock between batches even when
+/// producers keep the queue full.
+async fn apply(inner: Arc<Mutex<LFU>>, mut commands: mpsc::Receiver<Command>, batch: usize) {
+    while let Some(command) = commands.recv().await {
+        let mut flushed = Vec::new();
+        {
+            let mut lfu = inner.lock().expect("AsyncLfu lock poisoned");
+            let mut next = Some(command);
+            let mut applied = 0;
+            while let Some(command) = next {
+                match command {
+                    Command::Insert(key, value) => {
+                        lfu.insert(key, value);
+                    }
+                    Command::Flush(done) => flushed.push(done),
+                }
+                applied += 1;
+                next = if applied < batch {
+                    commands.try_recv().ok()
+                } else {
+                    None
+                };
+            }
+        }
+        for done in flushed {
+            let _ = done.send(());
+        }
+        // std mutex isn't fair, give waiting readers a chance before taking it again
+        tokio::task::yield_now().await;
+    }
+}
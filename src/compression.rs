@@ -0,0 +1,59 @@
+//! Pluggable value compression, see `LFU::compression`
+//!
+//! Values are compressed on insert and cache is sized against compressed length.
+
+use bytes::Bytes;
+use std::fmt;
+
+/// Compresses values before they're stored and restores them on the way out
+pub trait Compressor: fmt::Debug + Send + Sync {
+    fn compress(&self, data: &Bytes) -> Bytes;
+    fn decompress(&self, data: &Bytes) -> Bytes;
+}
+
+/// Stores values as they are
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoCompression;
+
+impl Compressor for NoCompression {
+    fn compress(&self, data: &Bytes) -> Bytes {
+        data.clone()
+    }
+
+    fn decompress(&self, data: &Bytes) -> Bytes {
+        data.clone()
+    }
+}
+
+/// gzip compression backed by flate2, enabled with `gzip` feature
+#[cfg(feature = "gzip")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Gzip;
+
+#[cfg(feature = "gzip")]
+impl Compressor for Gzip {
+    fn compress(&self, data: &Bytes) -> Bytes {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(data)
+            .expect("writing into a Vec can't fail");
+        Bytes::from(encoder.finish().expect("writing into a Vec can't fail"))
+    }
+
+    ///
+    /// Panics if data wasn't produced by `Gzip::compress`
+    ///
+    fn decompress(&self, data: &Bytes) -> Bytes {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&data[..])
+            .read_to_end(&mut decompressed)
+            .expect("value wasn't compressed with Gzip");
+        Bytes::from(decompressed)
+    }
+}
@@ -7,8 +7,31 @@
 //!
 //!
 
-use bytes::Bytes;
-use std::collections::{HashMap, VecDeque};
+#[cfg(feature = "tokio")]
+mod asynchronous;
+mod compression;
+mod sync;
+mod typed;
+mod wire;
+
+#[cfg(feature = "tokio")]
+pub use crate::asynchronous::AsyncLfu;
+#[cfg(feature = "gzip")]
+pub use crate::compression::Gzip;
+pub use crate::compression::{Compressor, NoCompression};
+pub use crate::sync::RwLfu;
+pub use crate::typed::TypedLfu;
+pub use crate::wire::{DecodeError, EncodeError};
+
+use bytes::{Bytes, BytesMut};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// how many evicted keys history remembers unless configured otherwise
+const DEFAULT_HISTORY_CAPACITY: usize = 1024;
 
 #[derive(Debug, Default)]
 struct FrequencyNode {
@@ -19,18 +42,107 @@ impl FrequencyNode {
     pub fn new() -> Self {
         FrequencyNode { items: vec![] }
     }
+
+    /// node at given index of a frequency list, growing the list with empty nodes if it's too short
+    fn at(frequency_list: &mut Vec<FrequencyNode>, idx: usize) -> &mut FrequencyNode {
+        if frequency_list.len() <= idx {
+            frequency_list.resize_with(idx + 1, FrequencyNode::new);
+        }
+        &mut frequency_list[idx]
+    }
+
+    /// add key keeping the node ordered by insertion sequence of its items, most recently inserted last
+    fn insert_key(&mut self, key: String, seq: u64, items: &HashMap<String, Item>) {
+        let idx = self
+            .items
+            .partition_point(|other| items.get(other).is_some_and(|item| item.seq < seq));
+        self.items.insert(idx, key);
+    }
 }
 
 /// original paper uses LFU Item but since this is private I see no reason for prefixing
-#[derive(Debug, Default)]
+#[derive(Debug, Clone)]
 struct Item {
     data: Bytes,
     parent: usize,
+    pinned: bool,
+    // how much of max_size this item takes
+    size: usize,
+    // caller supplied version, see LFU::insert_if_newer
+    version: u64,
+    // insertion sequence number, breaks ties between keys of the same frequency
+    seq: u64,
+    // set on insert and on every frequency bumping read
+    last_access: Instant,
+    // when the value was inserted, overwrites included
+    inserted_at: Instant,
+}
+
+/// boxed user callback, opaque in Debug output
+struct Hook<F: ?Sized>(Box<F>);
+
+impl<F: ?Sized> fmt::Debug for Hook<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Hook")
+    }
+}
+
+// callbacks are Send + Sync so LFU stays shareable between threads, e.g. behind RwLfu
+type InsertHook = Hook<dyn FnMut(&str, &Bytes) + Send + Sync>;
+type FullHook = Hook<dyn FnMut() + Send + Sync>;
+type EvictionFilter = Hook<dyn Fn(&str, &Bytes, usize) -> bool + Send + Sync>;
+
+/// xorshift64* generator driving `EvictionPolicy::WeightedRandom`, deterministic for a given seed
+#[derive(Debug, Clone, Copy)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // state must never be 0
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::new(0)
+    }
+}
+
+/// evicted key as remembered in history
+#[derive(Debug)]
+struct Eviction {
+    key: String,
+    frequency: usize,
+    evicted_at: Instant,
 }
 
 impl Item {
-    pub fn new(data: Bytes) -> Self {
-        Item { data, parent: 0 }
+    pub fn new(data: Bytes, size: usize) -> Self {
+        Item {
+            data,
+            parent: 0,
+            pinned: false,
+            size,
+            version: 0,
+            seq: 0,
+            last_access: Instant::now(),
+            inserted_at: Instant::now(),
+        }
     }
 }
 
@@ -48,10 +160,63 @@ pub struct LFU {
     // each cache has max allowed size for data, this does not include overhead coming
     // from implementation itself
     max_size: usize,
-    // this keeps track of size of heap stored Items data
+    // this keeps track of size of heap stored Items data, empty values count as a single byte
     current_size: usize,
     // useful extension of vect based LFU with history option
-    history: VecDeque<String>,
+    history: VecDeque<Eviction>,
+    // how many evicted keys history keeps, independent of max_size which is a byte budget
+    history_capacity: usize,
+    // keys the loader recently reported as absent, these don't count towards current_size,
+    // at most history_capacity of them are kept
+    tombstones: HashMap<String, Instant>,
+    // how long a miss stays cached, None disables negative caching
+    negative_ttl: Option<Duration>,
+    // frequency at which keys stop being promoted, None means no cap
+    max_frequency: Option<usize>,
+    // hard ceiling on frequency_list length, keys saturate at the top level
+    max_levels: Option<usize>,
+    // values are stored compressed when set, current_size tracks compressed length
+    compressor: Option<Box<dyn Compressor>>,
+    // called with every inserted entry, e.g. to write through to a backing store
+    on_insert: Option<InsertHook>,
+    // called when an insert first runs into max_size
+    on_full: Option<FullHook>,
+    // decides whether a key may be evicted, vetoed keys are skipped like pinned ones
+    eviction_filter: Option<EvictionFilter>,
+    // set while inserts keep running into max_size, so on_full fires once per transition
+    full: bool,
+    // data of most recent evictions, newest first, kept for re-admission when cache grows
+    retained: VecDeque<(String, Item)>,
+    // how many evicted entries keep their data, 0 disables retention
+    retained_capacity: usize,
+    // keys are lowercased on the way in when set
+    case_insensitive: bool,
+    // misses on keys that were recently evicted
+    thrash: u64,
+    // overwrites keep frequency of the entry they replace when set, otherwise it resets to 0
+    preserve_frequency: bool,
+    // frequency newly inserted keys start at
+    initial_frequency: usize,
+    // values longer than this are refused, None means no limit
+    max_value_size: Option<usize>,
+    // how victims are picked when making room
+    eviction_policy: EvictionPolicy,
+    // state of the generator used by randomized eviction policies
+    rng: Rng,
+    // cap on entries a single insert may evict, None means evict until the value fits
+    max_evictions: Option<usize>,
+    // limit on number of entries enforced alongside max_size, None means no limit
+    max_entries: Option<usize>,
+    // keys count towards current_size alongside their values when set
+    count_key_bytes: bool,
+    // high-water mark of frequency_list length, updated whenever frequency_list shrinks
+    max_levels_seen: usize,
+    // space reserved by reserve_slot for values still being computed, counted in current_size
+    reservations: HashMap<String, usize>,
+    // advanced by every sample_frequencies call so successive samples pick different keys
+    sample_cursor: AtomicUsize,
+    // sequence number handed to the next inserted item
+    next_seq: u64,
 }
 
 impl LFU {
@@ -64,6 +229,32 @@ impl LFU {
             tail_index: 0,
             frequency_list: vec![frequency_head],
             history: VecDeque::with_capacity(64),
+            history_capacity: DEFAULT_HISTORY_CAPACITY,
+            tombstones: HashMap::new(),
+            negative_ttl: None,
+            max_frequency: None,
+            max_levels: None,
+            compressor: None,
+            on_insert: None,
+            on_full: None,
+            eviction_filter: None,
+            full: false,
+            retained: VecDeque::new(),
+            retained_capacity: 0,
+            case_insensitive: false,
+            thrash: 0,
+            preserve_frequency: false,
+            initial_frequency: 0,
+            max_value_size: None,
+            eviction_policy: EvictionPolicy::Lfu,
+            rng: Rng::default(),
+            max_evictions: None,
+            max_entries: None,
+            count_key_bytes: false,
+            reservations: HashMap::new(),
+            max_levels_seen: 1,
+            sample_cursor: AtomicUsize::new(0),
+            next_seq: 0,
         }
     }
     ///
@@ -75,111 +266,177 @@ impl LFU {
     /// ```
     ///
     pub fn max_size(mut self, size: usize) -> Self {
+        self.set_max_size(size);
+        self
+    }
+    ///
+    /// Setter counterpart of `max_size` for configuring cache in place
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// let mut lfu = LFU::new();
+    /// lfu.set_max_size(1024).set_history_capacity(16);
+    /// ```
+    ///
+    pub fn set_max_size(&mut self, size: usize) -> &mut Self {
         self.max_size = size;
         self
     }
     ///
-    /// Check if we have value for this key
+    /// Builder disabling eviction entirely, same as `max_size(usize::MAX)`. Inserts skip the eviction
+    /// pass altogether while current_size still tracks usage.
     ///
     /// ```
     /// use lfu_vecs::LFU;
     /// use bytes::Bytes;
-    /// let mut lfu = LFU::new().max_size(1024);
-    /// assert_eq!(lfu.contains_key("a"), false);
-    /// lfu.insert("a".to_string(), Bytes::from("a"));
-    /// assert_eq!(lfu.contains_key("a"), true);
+    /// let mut lfu = LFU::new().unbounded();
+    /// lfu.insert("a".to_string(), Bytes::from(vec![0; 1 << 16]));
+    /// assert_eq!(lfu.current_size(), 1 << 16);
     /// ```
     ///
-    pub fn contains_key(&self, key: &str) -> bool {
-        self.items.contains_key(key)
+    pub fn unbounded(self) -> Self {
+        self.max_size(usize::MAX)
     }
-
     ///
-    /// Check how many items there currently is in cache
+    /// Builder limiting number of entries on top of max_size, inserts evict until both limits hold
     ///
     /// ```
     /// use lfu_vecs::LFU;
     /// use bytes::Bytes;
-    /// let mut lfu = LFU::new();
-    /// assert_eq!(lfu.current_size(), 0);
-    /// lfu.insert("a".to_string(), Bytes::from("b"));
-    /// assert_eq!(lfu.current_size(), 1);
+    /// let mut lfu = LFU::new().max_size(1024).max_entries(2);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// lfu.insert("c".to_string(), Bytes::from("44"));
+    /// assert_eq!(lfu.len(), 2);
     /// ```
     ///
-    pub fn current_size(&self) -> usize {
-        self.current_size
+    pub fn max_entries(mut self, n: usize) -> Self {
+        self.set_max_entries(n);
+        self
     }
     ///
-    /// Allows to check frequency for a key of given value
+    /// Setter counterpart of `max_entries`, like `set_max_size` it applies from the next insert on
     ///
     /// ```
     /// use lfu_vecs::LFU;
     /// use bytes::Bytes;
-    /// let mut lfu = LFU::new();
-    /// lfu.get_frequency("a");
-    /// lfu.insert("a".to_string(), Bytes::from("b"));
-    /// assert_eq!(lfu.get_frequency("a"), 0);
-    /// lfu.get("a");
-    /// assert_eq!(lfu.get_frequency("a"), 1);
-    /// lfu.get("a");
-    /// assert_eq!(lfu.get_frequency("a"), 2);
-    /// lfu.get("a");
-    /// assert_eq!(lfu.get_frequency("a"), 3);
+    /// let mut lfu = LFU::new().max_size(1024);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// lfu.set_max_entries(2);
+    /// lfu.insert("c".to_string(), Bytes::from("44"));
+    /// assert_eq!(lfu.len(), 2);
     /// ```
-    pub fn get_frequency(&mut self, key: &str) -> usize {
-        match self.items.get(key) {
-            Some(item) => item.parent,
-            _ => 0,
-        }
+    ///
+    pub fn set_max_entries(&mut self, n: usize) -> &mut Self {
+        self.max_entries = Some(n);
+        self
     }
-
     ///
-    /// Get a Some(value) or None for a given key
+    /// Builder for number of evicted keys remembered in history, defaults to 1024
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// let lfu = LFU::new().max_size(1 << 30).history_capacity(128);
+    /// ```
+    ///
+    pub fn history_capacity(mut self, capacity: usize) -> Self {
+        self.set_history_capacity(capacity);
+        self
+    }
     ///
+    /// Change max_size and, when it grows, re-admit entries retained by `retain_evicted_data`,
+    /// coldest first, with frequencies they had when evicted. Entries that don't fit in the new
+    /// budget or under `max_entries` stay retained. Returns number of re-admitted entries.
     ///
     /// ```
     /// use lfu_vecs::LFU;
     /// use bytes::Bytes;
-    /// let mut lfu = LFU::new();
-    /// assert_eq!(lfu.get("a"), None);
-    /// lfu.insert("a".to_string(), Bytes::from("b"));
-    /// assert_eq!(lfu.get("a"), Some(&Bytes::from("b")));
+    /// let mut lfu = LFU::new().max_size(3).retain_evicted_data(8);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// assert_eq!(lfu.contains_key("a"), false);
+    /// assert_eq!(lfu.set_max_size_and_readmit(16), 1);
+    /// assert_eq!(lfu.get("a"), Some(Bytes::from("42")));
     /// ```
-    pub fn get(&mut self, key: &str) -> Option<&Bytes> {
-        if let Some(item) = self.items.get_mut(key) {
-            if let Some(frequency_node) = self.frequency_list.get_mut(item.parent) {
-                frequency_node.items.retain(|x| x != key);
-            }
-            item.parent += 1;
-            match self.frequency_list.get_mut(item.parent) {
-                Some(frequency_node) => {
-                    // we have the next fnode
-                    frequency_node.items.push(key.to_owned());
+    pub fn set_max_size_and_readmit(&mut self, size: usize) -> usize {
+        let grew = size > self.max_size;
+        self.set_max_size(size);
+        if !grew {
+            return 0;
+        }
+        let mut retained: Vec<Option<(String, Item)>> = self.retained.drain(..).map(Some).collect();
+        let mut coldest_first: Vec<usize> = (0..retained.len()).collect();
+        coldest_first.sort_by_key(|&index| retained[index].as_ref().map(|(_, item)| item.parent));
+        let mut readmitted = 0;
+        for index in coldest_first {
+            let fits = match &retained[index] {
+                Some((key, _)) if self.items.contains_key(key) => {
+                    // key was inserted again since, retained data is stale
+                    retained[index] = None;
+                    false
                 }
-                None => {
-                    // we need to add a node
-                    let mut frequency_node = FrequencyNode::new();
-                    frequency_node.items.push(key.to_owned());
-                    self.frequency_list.push(frequency_node);
+                Some((_, item)) => {
+                    self.current_size + item.size < self.max_size
+                        && !self.at_entry_limit(self.items.len())
+                }
+                None => false,
+            };
+            if fits {
+                if let Some((key, item)) = retained[index].take() {
+                    self.attach_item(key, item);
+                    readmitted += 1;
                 }
             }
-            Some(&item.data)
-        } else {
-            None
         }
+        self.retained = retained.into_iter().flatten().collect();
+        readmitted
     }
     ///
-    /// Record evicted key in history
+    /// Consume the cache producing a freshly laid out one limited to `new_max_size`, with the same
+    /// settings. Entries keep their frequencies; hottest ones are kept first and coldest are evicted
+    /// into history when they don't fit. Pinned entries are always kept.
     ///
-    fn add_to_history(&mut self, dropped_key: String) {
-        while self.history.len() > self.max_size {
-            self.history.pop_back();
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// lfu.get("a");
+    /// let mut lfu = lfu.rebuild(3);
+    /// assert_eq!(lfu.key_snapshot(), vec!["a".to_string()]);
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    pub fn rebuild(mut self, new_max_size: usize) -> LFU {
+        let mut entries: Vec<(String, Item)> = self.items.drain().collect();
+        // hottest first, within a frequency the entry eviction would take last goes first
+        entries.sort_by_key(|(_, item)| (!item.pinned, std::cmp::Reverse(item.parent), item.seq));
+        self.note_levels();
+        self.frequency_list = vec![FrequencyNode::new()];
+        // reservations stay, they were made against the cache and still count
+        self.current_size = self.reservations.values().sum();
+        self.max_size = new_max_size;
+        self.full = false;
+        let mut kept = vec![];
+        let mut size = self.current_size;
+        for (key, item) in entries {
+            if item.pinned || (size + item.size < new_max_size && !self.at_entry_limit(kept.len()))
+            {
+                size += item.size;
+                kept.push((key, item));
+            } else {
+                self.add_to_history(key, item.parent);
+            }
+        }
+        for (key, item) in kept.into_iter().rev() {
+            self.attach_item(key, item);
         }
-        self.history.push_front(dropped_key);
+        self
     }
     ///
-    /// Check if key was recently dropped from cache. History has same size as max_size (remembers max_size keys)
-    ///
+    /// Setter counterpart of `history_capacity`. Shrinking it drops oldest evictions right away
+    /// and releases memory they took.
     ///
     /// ```
     /// use lfu_vecs::LFU;
@@ -187,92 +444,5262 @@ impl LFU {
     /// let mut lfu = LFU::new().max_size(3);
     /// lfu.insert("a".to_string(), Bytes::from("42"));
     /// lfu.insert("b".to_string(), Bytes::from("43"));
-    /// lfu.insert("c".to_string(), Bytes::from("43"));
-    /// assert_eq!(lfu.has_evicted_recently("a"), true);
+    /// lfu.insert("c".to_string(), Bytes::from("44"));
+    /// lfu.set_history_capacity(1);
+    /// assert_eq!(lfu.has_evicted_recently("a"), false);
+    /// assert_eq!(lfu.has_evicted_recently("b"), true);
     /// ```
-
-    pub fn has_evicted_recently(&self, key: &str) -> bool {
-        self.history
-            .iter()
-            .any(|historical_key| historical_key.eq(key))
+    pub fn set_history_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.history_capacity = capacity;
+        if self.history.len() > capacity {
+            self.history.truncate(capacity);
+            self.history.shrink_to(capacity);
+        }
+        self
     }
-
     ///
-    /// Insert a value into LFU
+    /// Builder preallocating `levels` frequency nodes so `get` doesn't have to grow
+    /// frequency_list until keys get more frequent than that
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// let lfu = LFU::new().prealloc_levels(32);
+    /// ```
+    ///
+    pub fn prealloc_levels(mut self, levels: usize) -> Self {
+        if self.frequency_list.len() < levels {
+            self.frequency_list.resize_with(levels, FrequencyNode::new);
+        }
+        self
+    }
+    ///
+    /// Builder capping frequency of a single key, once a key gets to `cap` further reads don't promote it.
+    /// This keeps frequency_list bounded and lets permanently hot keys be evicted once they go stale.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// let lfu = LFU::new().max_frequency_cap(100);
+    /// ```
+    ///
+    pub fn max_frequency_cap(mut self, cap: usize) -> Self {
+        self.set_max_frequency_cap(cap);
+        self
+    }
+    ///
+    /// Setter counterpart of `max_frequency_cap`
+    ///
+    pub fn set_max_frequency_cap(&mut self, cap: usize) -> &mut Self {
+        self.max_frequency = Some(cap);
+        self
+    }
+    ///
+    /// Builder bounding number of frequency levels. Keys that would advance past the last level
+    /// stay there, so frequency_list never grows over `levels` nodes regardless of workload.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// let lfu = LFU::new().max_levels(16);
+    /// ```
+    ///
+    pub fn max_levels(mut self, levels: usize) -> Self {
+        self.set_max_levels(levels);
+        self
+    }
+    ///
+    /// Setter counterpart of `max_levels`
+    ///
+    pub fn set_max_levels(&mut self, levels: usize) -> &mut Self {
+        self.max_levels = Some(levels);
+        self
+    }
+    ///
+    /// Builder for value compression. Values are compressed on insert and max_size applies to
+    /// compressed length. Reads like `get`, `peek` or `take` decompress, only `iter_mut`, `diff`
+    /// and `eviction_filter` see values as stored. There's no setter, values already stored couldn't
+    /// be read back under a different compressor.
+    ///
+    /// ```
+    /// use lfu_vecs::{NoCompression, LFU};
+    /// let lfu = LFU::new().compression(NoCompression);
+    /// ```
+    ///
+    pub fn compression(mut self, compressor: impl Compressor + 'static) -> Self {
+        self.compressor = Some(Box::new(compressor));
+        self
+    }
     ///
+    /// Builder for a callback invoked with key and (uncompressed) value after every insert,
+    /// overwrites included. Useful for write-through caches. The callback has to be `Send + Sync`
+    /// so the cache can be shared between threads with `RwLfu`.
     ///
     /// ```
     /// use lfu_vecs::LFU;
     /// use bytes::Bytes;
-    /// let mut lfu = LFU::new();
+    /// use std::sync::{Arc, Mutex};
+    /// let written = Arc::new(Mutex::new(vec![]));
+    /// let store = Arc::clone(&written);
+    /// let mut lfu = LFU::new().on_insert(move |key, value| {
+    ///     store.lock().unwrap().push((key.to_string(), value.clone()))
+    /// });
     /// lfu.insert("a".to_string(), Bytes::from("b"));
-    /// lfu.insert("a".to_string(), Bytes::from("z"));
-    /// assert_eq!(lfu.get("a"), Some(&Bytes::from("z")));
+    /// assert_eq!(*written.lock().unwrap(), vec![("a".to_string(), Bytes::from("b"))]);
     /// ```
-    pub fn insert(&mut self, key: String, value: Bytes) -> Option<Bytes> {
-        let mut fnode_index = 0_usize;
-        while self.current_size + value.len() >= self.max_size {
-            if let Some(frequency_node) = self.frequency_list.get_mut(fnode_index) {
-                if let Some(key) = frequency_node.items.pop() {
-                    if let Some(item) = self.items.remove(&key) {
-                        self.current_size -= item.data.len();
-                        self.add_to_history(key);
-                    }
-                };
-            }
-            if fnode_index == self.frequency_list.len() {
-                break;
-            }
-            fnode_index += 1;
-        }
-
-        self.current_size += value.len();
-        let previous = match self.items.insert(key.clone(), Item::new(value)) {
-            Some(previous) => Some(previous.data),
-            None => None,
-        };
-        match self.frequency_list.get_mut(0) {
-            Some(frequency_node) => frequency_node.items.push(key),
-            _ => unreachable!(),
-        }
-        previous
+    ///
+    pub fn on_insert(mut self, f: impl FnMut(&str, &Bytes) + Send + Sync + 'static) -> Self {
+        self.on_insert = Some(Hook(Box::new(f)));
+        self
     }
-}
-
-#[cfg(test)]
-mod tests {
-
-    use crate::*;
-    use bytes::Bytes;
-
-    #[test]
-    fn it_works() {
-        let mut lfu = LFU::new();
-        lfu.insert("a".to_string(), Bytes::from("42"));
-        assert_eq!(lfu.get(&"a".to_string()), Some(&Bytes::from("42")));
+    ///
+    /// Builder for a callback invoked when an insert runs into max_size and has to evict. It fires once per
+    /// transition: further over-budget inserts stay silent until an insert fits without evicting.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// let fired = Arc::new(AtomicUsize::new(0));
+    /// let counter = Arc::clone(&fired);
+    /// let mut lfu = LFU::new().max_size(3).on_full(move || {
+    ///     counter.fetch_add(1, Ordering::Relaxed);
+    /// });
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// assert_eq!(fired.load(Ordering::Relaxed), 0);
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// lfu.insert("c".to_string(), Bytes::from("44"));
+    /// assert_eq!(fired.load(Ordering::Relaxed), 1);
+    /// ```
+    ///
+    pub fn on_full(mut self, f: impl FnMut() + Send + Sync + 'static) -> Self {
+        self.on_full = Some(Hook(Box::new(f)));
+        self
     }
-    #[test]
-    fn test_max_size() {
-        let lfu = LFU::new().max_size(1000);
-        assert_eq!(lfu.max_size, 1000);
+    ///
+    /// Builder for a callback deciding whether a key may be evicted, called with key, value as stored
+    /// and frequency. Returning false vetoes eviction of that key and the next candidate is tried instead,
+    /// moving on to hotter frequency nodes when a whole node is vetoed. If everything is vetoed the insert
+    /// goes over max_size, just like when every key is pinned.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new()
+    ///     .max_size(5)
+    ///     .eviction_filter(|key, _, _| !key.starts_with("expensive:"));
+    /// lfu.insert("cheap".to_string(), Bytes::from("42"));
+    /// lfu.insert("expensive:1".to_string(), Bytes::from("43"));
+    /// lfu.insert("new".to_string(), Bytes::from("44"));
+    /// assert_eq!(lfu.contains_key("expensive:1"), true);
+    /// assert_eq!(lfu.contains_key("cheap"), false);
+    /// ```
+    ///
+    pub fn eviction_filter(
+        mut self,
+        f: impl Fn(&str, &Bytes, usize) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.eviction_filter = Some(Hook(Box::new(f)));
+        self
     }
-
-    #[test]
-    fn test_evictions() {
-        let mut lfu = LFU::new().max_size(5);
-        lfu.insert("a".to_string(), Bytes::from("42"));
-        lfu.insert("b".to_string(), Bytes::from("43"));
-        lfu.insert("c".to_string(), Bytes::from("43"));
-        lfu.insert("d".to_string(), Bytes::from("43"));
-        assert_eq!(lfu.current_size(), 4);
+    ///
+    /// Builder keeping data of last `n` evicted entries so `set_max_size_and_readmit` can bring
+    /// them back. Retained data doesn't count towards current_size.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// let lfu = LFU::new().retain_evicted_data(16);
+    /// ```
+    ///
+    pub fn retain_evicted_data(mut self, n: usize) -> Self {
+        self.set_retain_evicted_data(n);
+        self
     }
-
-    #[test]
-    fn test_frequency() {
-        let mut lfu = LFU::new().max_size(3);
-        lfu.insert("a".to_string(), Bytes::from("42"));
-        lfu.get("a");
-        lfu.get("a");
-        assert_eq!(lfu.get_frequency("a"), 2);
+    ///
+    /// Setter counterpart of `retain_evicted_data`, shrinking drops data of the oldest evictions
+    ///
+    pub fn set_retain_evicted_data(&mut self, n: usize) -> &mut Self {
+        self.retained_capacity = n;
+        self.retained.truncate(n);
+        self
+    }
+    ///
+    /// Builder for case insensitive keys. When enabled keys are stored lowercased, so `insert`, `get`,
+    /// `peek`, `remove`, `contains_key` and frequency lookups match regardless of case and
+    /// keys handed back by the cache come in lowercase form. There's no setter, switching it on a
+    /// populated cache would strand keys stored in the other form.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().case_insensitive(true);
+    /// lfu.insert("Foo".to_string(), Bytes::from("bar"));
+    /// assert_eq!(lfu.get("FOO"), Some(Bytes::from("bar")));
+    /// ```
+    ///
+    pub fn case_insensitive(mut self, enabled: bool) -> Self {
+        self.case_insensitive = enabled;
+        self
+    }
+    ///
+    /// Builder for overwrite semantics. By default inserting over an existing key resets its frequency
+    /// to `initial_frequency`,
+    /// when enabled the new value keeps frequency of the one it replaces.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().preserve_frequency_on_overwrite(true);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// lfu.insert("a".to_string(), Bytes::from("c"));
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    ///
+    pub fn preserve_frequency_on_overwrite(mut self, enabled: bool) -> Self {
+        self.set_preserve_frequency_on_overwrite(enabled);
+        self
+    }
+    ///
+    /// Setter counterpart of `preserve_frequency_on_overwrite`
+    ///
+    pub fn set_preserve_frequency_on_overwrite(&mut self, enabled: bool) -> &mut Self {
+        self.preserve_frequency = enabled;
+        self
+    }
+    ///
+    /// Builder for frequency new keys start at, 0 by default. A small head start keeps fresh inserts from
+    /// being the first eviction candidates before they get a chance to be read.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().initial_frequency(1);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    ///
+    pub fn initial_frequency(mut self, frequency: usize) -> Self {
+        self.set_initial_frequency(frequency);
+        self
+    }
+    ///
+    /// Setter counterpart of `initial_frequency`, keys already cached keep their frequency
+    ///
+    pub fn set_initial_frequency(&mut self, frequency: usize) -> &mut Self {
+        self.initial_frequency = frequency;
+        self
+    }
+    ///
+    /// Builder for largest accepted value length, independent of max_size. Inserts of longer values
+    /// are refused leaving cache untouched, `try_insert` reports them as an error.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_value_size(2);
+    /// lfu.insert("a".to_string(), Bytes::from("bcd"));
+    /// assert_eq!(lfu.contains_key("a"), false);
+    /// ```
+    ///
+    pub fn max_value_size(mut self, size: usize) -> Self {
+        self.set_max_value_size(size);
+        self
+    }
+    ///
+    /// Setter counterpart of `max_value_size`, values already cached stay even if longer
+    ///
+    pub fn set_max_value_size(&mut self, size: usize) -> &mut Self {
+        self.max_value_size = Some(size);
+        self
+    }
+    ///
+    /// Builder for how victims are picked when making room, `EvictionPolicy::Lfu` by default
+    ///
+    /// ```
+    /// use lfu_vecs::{EvictionPolicy, LFU};
+    /// let lfu = LFU::new().eviction_policy(EvictionPolicy::WeightedRandom);
+    /// ```
+    ///
+    pub fn eviction_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.set_eviction_policy(policy);
+        self
+    }
+    ///
+    /// Setter counterpart of `eviction_policy`
+    ///
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) -> &mut Self {
+        self.eviction_policy = policy;
+        self
+    }
+    ///
+    /// Builder seeding the generator behind randomized eviction policies. Same seed and same sequence
+    /// of calls always evict the same keys.
+    ///
+    /// ```
+    /// use lfu_vecs::{EvictionPolicy, LFU};
+    /// let lfu = LFU::new()
+    ///     .eviction_policy(EvictionPolicy::WeightedRandom)
+    ///     .eviction_seed(42);
+    /// ```
+    ///
+    pub fn eviction_seed(mut self, seed: u64) -> Self {
+        self.set_eviction_seed(seed);
+        self
+    }
+    ///
+    /// Setter counterpart of `eviction_seed`, restarts the generator from `seed`
+    ///
+    pub fn set_eviction_seed(&mut self, seed: u64) -> &mut Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+    ///
+    /// Builder capping how many entries a single insert may evict, bounding worst case insert latency.
+    /// When the cap is hit the cache temporarily goes over max_size, following inserts evict further.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(5).max_evictions_per_insert(1);
+    /// lfu.insert_with_frequency("a".to_string(), Bytes::from("4"), 0);
+    /// lfu.insert_with_frequency("b".to_string(), Bytes::from("2"), 1);
+    /// lfu.insert("c".to_string(), Bytes::from("4242"));
+    /// assert_eq!(lfu.len(), 2);
+    /// assert_eq!(lfu.current_size(), 5);
+    /// ```
+    ///
+    pub fn max_evictions_per_insert(mut self, n: usize) -> Self {
+        self.set_max_evictions_per_insert(n);
+        self
+    }
+    ///
+    /// Setter counterpart of `max_evictions_per_insert`
+    ///
+    pub fn set_max_evictions_per_insert(&mut self, n: usize) -> &mut Self {
+        self.max_evictions = Some(n);
+        self
+    }
+    ///
+    /// Builder charging key length against max_size alongside the value, for a truer footprint
+    /// of workloads with small values and large keys
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().count_key_bytes(true);
+    /// lfu.insert("abc".to_string(), Bytes::from("d"));
+    /// assert_eq!(lfu.current_size(), 4);
+    /// ```
+    ///
+    pub fn count_key_bytes(mut self, enabled: bool) -> Self {
+        self.set_count_key_bytes(enabled);
+        self
+    }
+    ///
+    /// Setter counterpart of `count_key_bytes`, applies to entries inserted from now on
+    ///
+    pub fn set_count_key_bytes(&mut self, enabled: bool) -> &mut Self {
+        self.count_key_bytes = enabled;
+        self
+    }
+    ///
+    /// Builder enabling negative caching: when loader passed to `get_or_load` returns None
+    /// the miss is remembered for `ttl` and the loader isn't invoked again for that key until it expires.
+    /// Like evicted keys, at most `history_capacity` misses are remembered; expired ones are purged
+    /// first and then the oldest make room.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use std::time::Duration;
+    /// let lfu = LFU::new().negative_cache(Duration::from_secs(5));
+    /// ```
+    ///
+    pub fn negative_cache(mut self, ttl: Duration) -> Self {
+        self.set_negative_cache(ttl);
+        self
+    }
+    ///
+    /// Setter counterpart of `negative_cache`
+    ///
+    pub fn set_negative_cache(&mut self, ttl: Duration) -> &mut Self {
+        self.negative_ttl = Some(ttl);
+        self
+    }
+    ///
+    /// Every tunable at once, e.g. for logging. Callbacks and compressor are only reported as set or not.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// let lfu = LFU::new().max_size(1024).case_insensitive(true);
+    /// let config = lfu.config();
+    /// assert_eq!(config.max_size, 1024);
+    /// assert_eq!(config.case_insensitive, true);
+    /// ```
+    ///
+    pub fn config(&self) -> LfuConfig {
+        LfuConfig {
+            max_size: self.max_size,
+            history_capacity: self.history_capacity,
+            negative_cache: self.negative_ttl,
+            max_frequency_cap: self.max_frequency,
+            max_levels: self.max_levels,
+            compression: self.compressor.is_some(),
+            retain_evicted_data: self.retained_capacity,
+            case_insensitive: self.case_insensitive,
+            preserve_frequency_on_overwrite: self.preserve_frequency,
+            initial_frequency: self.initial_frequency,
+            max_value_size: self.max_value_size,
+            count_key_bytes: self.count_key_bytes,
+            max_evictions_per_insert: self.max_evictions,
+            max_entries: self.max_entries,
+            eviction_policy: self.eviction_policy,
+            on_insert: self.on_insert.is_some(),
+            on_full: self.on_full.is_some(),
+            eviction_filter: self.eviction_filter.is_some(),
+        }
+    }
+    ///
+    /// Check if we have value for this key
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(1024);
+    /// assert_eq!(lfu.contains_key("a"), false);
+    /// lfu.insert("a".to_string(), Bytes::from("a"));
+    /// assert_eq!(lfu.contains_key("a"), true);
+    /// ```
+    ///
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.items.contains_key(self.normalize(key).as_ref())
+    }
+    ///
+    /// Check if every given key is cached, true for no keys
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.contains_all(&["a"]), true);
+    /// assert_eq!(lfu.contains_all(&["a", "c"]), false);
+    /// ```
+    ///
+    pub fn contains_all(&self, keys: &[&str]) -> bool {
+        keys.iter().all(|key| self.contains_key(key))
+    }
+    ///
+    /// Check if at least one of given keys is cached, false for no keys
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.contains_any(&["a", "c"]), true);
+    /// assert_eq!(lfu.contains_any(&["c"]), false);
+    /// ```
+    ///
+    pub fn contains_any(&self, keys: &[&str]) -> bool {
+        keys.iter().any(|key| self.contains_key(key))
+    }
+
+    ///
+    /// Check how many items there currently is in cache
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.current_size(), 0);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.current_size(), 1);
+    /// ```
+    ///
+    pub fn current_size(&self) -> usize {
+        self.current_size
+    }
+    ///
+    /// Fill ratio of the cache, current_size over max_size. 0.0 for a zero sized cache.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(10);
+    /// assert_eq!(lfu.utilization(), 0.0);
+    /// lfu.insert("a".to_string(), Bytes::from("bc"));
+    /// assert_eq!(lfu.utilization(), 0.2);
+    /// lfu.insert("b".to_string(), Bytes::from("bcdefg"));
+    /// assert_eq!(lfu.utilization(), 0.8);
+    /// ```
+    ///
+    pub fn utilization(&self) -> f64 {
+        if self.max_size == 0 {
+            return 0.0;
+        }
+        self.current_size as f64 / self.max_size as f64
+    }
+    ///
+    /// Room left under each limit as (max_size - current_size, max_entries - len), saturating at 0.
+    /// Tells whether bytes or entry count is the tighter constraint before inserting. Without
+    /// `max_entries` entry headroom is usize::MAX.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(10).max_entries(3);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// assert_eq!(lfu.headroom(), (8, 2));
+    /// ```
+    ///
+    pub fn headroom(&self) -> (usize, usize) {
+        (
+            self.max_size.saturating_sub(self.current_size),
+            self.max_entries
+                .map_or(usize::MAX, |limit| limit.saturating_sub(self.items.len())),
+        )
+    }
+    ///
+    /// Number of entries in cache
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.len(), 0);
+    /// assert_eq!(lfu.is_empty(), true);
+    /// lfu.insert("a".to_string(), Bytes::from("bc"));
+    /// assert_eq!(lfu.len(), 1);
+    /// ```
+    ///
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    ///
+    /// Check if there are no entries in cache
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    ///
+    /// How many times `get` or `get_or_load` missed a key that was recently evicted.
+    /// Steadily growing value means cache is too small for its working set.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(3);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// lfu.get("a");
+    /// lfu.get("c");
+    /// assert_eq!(lfu.thrash_count(), 1);
+    /// ```
+    ///
+    pub fn thrash_count(&self) -> u64 {
+        self.thrash
+    }
+    ///
+    /// Allows to check frequency for a key of given value
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.get_frequency("a");
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.get_frequency("a"), 0);
+    /// lfu.get("a");
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// lfu.get("a");
+    /// assert_eq!(lfu.get_frequency("a"), 2);
+    /// lfu.get("a");
+    /// assert_eq!(lfu.get_frequency("a"), 3);
+    /// ```
+    pub fn get_frequency(&mut self, key: &str) -> usize {
+        match self.items.get(self.normalize(key).as_ref()) {
+            Some(item) => item.parent,
+            _ => 0,
+        }
+    }
+
+    ///
+    /// Snapshot of frequencies for every key currently in cache
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.frequency_map().get("a"), Some(&1));
+    /// ```
+    pub fn frequency_map(&self) -> HashMap<String, usize> {
+        self.items
+            .iter()
+            .map(|(key, item)| (key.clone(), item.parent))
+            .collect()
+    }
+
+    ///
+    /// Frequency of every key in ascending order, e.g. for computing percentiles. Built by walking
+    /// frequency nodes in order, so no sorting is needed.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.frequencies_sorted(), vec![0, 1]);
+    /// ```
+    pub fn frequencies_sorted(&self) -> Vec<usize> {
+        let mut frequencies = Vec::with_capacity(self.items.len());
+        for (level, frequency_node) in self.frequency_list.iter().enumerate() {
+            frequencies.extend(std::iter::repeat_n(level, frequency_node.items.len()));
+        }
+        frequencies
+    }
+    ///
+    /// Keys at a given frequency level, empty if nothing reached it. Order within a level reflects when
+    /// keys got there and carries no further meaning.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.level_keys(0), ["a".to_string()]);
+    /// assert!(lfu.level_keys(7).is_empty());
+    /// ```
+    pub fn level_keys(&self, level: usize) -> &[String] {
+        self.frequency_list
+            .get(level)
+            .map_or(&[], |frequency_node| frequency_node.items.as_slice())
+    }
+
+    ///
+    /// Iterate over keys together with index of the frequency node they are actually placed in,
+    /// coldest nodes first. Unlike `frequency_map` this walks frequency nodes rather than items.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// let levels: Vec<(&String, usize)> = lfu.iter_with_level().collect();
+    /// assert_eq!(levels, vec![(&"c".to_string(), 0), (&"a".to_string(), 1)]);
+    /// ```
+    pub fn iter_with_level(&self) -> impl Iterator<Item = (&String, usize)> {
+        self.frequency_list
+            .iter()
+            .enumerate()
+            .flat_map(|(level, frequency_node)| {
+                frequency_node.items.iter().map(move |key| (key, level))
+            })
+    }
+
+    ///
+    /// Replace every key's frequency with result of `f(key, frequency)` and rebuild frequency nodes,
+    /// e.g. to implement custom aging
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert_with_frequency("a".to_string(), Bytes::from("b"), 8);
+    /// lfu.update_frequencies(|_, frequency| frequency / 2);
+    /// assert_eq!(lfu.get_frequency("a"), 4);
+    /// ```
+    pub fn update_frequencies(&mut self, f: impl Fn(&str, usize) -> usize) {
+        let top_level = self.top_level();
+        let mut frequency_list = vec![FrequencyNode::new()];
+        for (key, item) in self.items.iter_mut() {
+            item.parent = f(key, item.parent).min(top_level);
+            FrequencyNode::at(&mut frequency_list, item.parent)
+                .items
+                .push(key.clone());
+        }
+        for frequency_node in frequency_list.iter_mut() {
+            frequency_node
+                .items
+                .sort_unstable_by_key(|key| self.items[key].seq);
+        }
+        self.note_levels();
+        self.frequency_list = frequency_list;
+    }
+    ///
+    /// Mean frequency across cached entries, 0.0 for empty cache. Values close to 0 mean most entries
+    /// are one-hit wonders, high values suggest cache is too small for the working set.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.average_frequency(), 0.0);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.average_frequency(), 1.0);
+    /// ```
+    pub fn average_frequency(&self) -> f64 {
+        if self.items.is_empty() {
+            return 0.0;
+        }
+        let total: usize = self.items.values().map(|item| item.parent).sum();
+        total as f64 / self.len() as f64
+    }
+    ///
+    /// Frequencies of up to `n` keys spread evenly across the cache, a cheap estimate of frequency
+    /// distribution that avoids building a full histogram. Keys are walked from lowest frequency up,
+    /// so equal caches give equal samples, each call starts at a different offset so repeated calls
+    /// cover different keys.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.sample_frequencies(10), vec![1]);
+    /// ```
+    pub fn sample_frequencies(&self, n: usize) -> Vec<usize> {
+        if n == 0 || self.items.is_empty() {
+            return vec![];
+        }
+        let step = (self.items.len() / n).max(1);
+        let offset = self.sample_cursor.fetch_add(1, Ordering::Relaxed) % step;
+        self.frequency_list
+            .iter()
+            .enumerate()
+            .flat_map(|(level, frequency_node)| frequency_node.items.iter().map(move |_| level))
+            .skip(offset)
+            .step_by(step)
+            .take(n)
+            .collect()
+    }
+    ///
+    /// Pin a key so it's never evicted, returns whether the key exists. Pins survive overwrites.
+    /// When every remaining key is pinned eviction can't make room and `insert` goes over max_size.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(3);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// assert_eq!(lfu.pin("a"), true);
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// assert_eq!(lfu.contains_key("a"), true);
+    /// assert_eq!(lfu.current_size(), 4);
+    /// ```
+    pub fn pin(&mut self, key: &str) -> bool {
+        let key = self.normalize(key);
+        match self.items.get_mut(key.as_ref()) {
+            Some(item) => {
+                item.pinned = true;
+                true
+            }
+            None => false,
+        }
+    }
+    ///
+    /// Make a pinned key evictable again, returns whether the key exists
+    ///
+    pub fn unpin(&mut self, key: &str) -> bool {
+        let key = self.normalize(key);
+        match self.items.get_mut(key.as_ref()) {
+            Some(item) => {
+                item.pinned = false;
+                true
+            }
+            None => false,
+        }
+    }
+    ///
+    /// Length of the biggest value in cache, 0 for empty cache
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.largest_value_size(), 0);
+    /// lfu.insert("a".to_string(), Bytes::from("bcd"));
+    /// assert_eq!(lfu.largest_value_size(), 3);
+    /// ```
+    pub fn largest_value_size(&self) -> usize {
+        self.items
+            .values()
+            .map(|item| item.data.len())
+            .max()
+            .unwrap_or(0)
+    }
+    ///
+    /// Key holding the biggest value, ties go to the lexicographically smallest key
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("de"));
+    /// assert_eq!(lfu.largest_key(), Some(&"c".to_string()));
+    /// ```
+    pub fn largest_key(&self) -> Option<&String> {
+        self.items
+            .iter()
+            .max_by(|(key_a, a), (key_b, b)| {
+                a.data
+                    .len()
+                    .cmp(&b.data.len())
+                    .then_with(|| key_b.cmp(key_a))
+            })
+            .map(|(key, _)| key)
+    }
+    ///
+    /// Keys paired with their value length, largest first, ties ordered by key
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("def"));
+    /// assert_eq!(lfu.iter_by_size_desc(), vec![(&"c".to_string(), 3), (&"a".to_string(), 1)]);
+    /// ```
+    pub fn iter_by_size_desc(&self) -> Vec<(&String, usize)> {
+        let mut entries: Vec<(&String, usize)> = self
+            .items
+            .iter()
+            .map(|(key, item)| (key, item.data.len()))
+            .collect();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        entries
+    }
+    ///
+    /// Bytes held by entries accessed at least `min_frequency` times, an estimate of the hot working set
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("bc"));
+    /// lfu.insert("d".to_string(), Bytes::from("e"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.working_set_bytes(1), 2);
+    /// ```
+    pub fn working_set_bytes(&self, min_frequency: usize) -> usize {
+        self.items
+            .values()
+            .filter(|item| item.parent >= min_frequency)
+            .map(|item| item.data.len())
+            .sum()
+    }
+    ///
+    /// Share of current_size held by entries accessed at least `min_frequency` times, 0.0 for empty cache.
+    /// Low values mean most bytes are cold and could go to a cheaper tier.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.hot_byte_fraction(1), 0.0);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("def"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.hot_byte_fraction(1), 0.25);
+    /// ```
+    pub fn hot_byte_fraction(&self, min_frequency: usize) -> f64 {
+        if self.current_size == 0 {
+            return 0.0;
+        }
+        self.working_set_bytes(min_frequency) as f64 / self.current_size as f64
+    }
+    ///
+    /// Owned copy of every cached key, lets callers walk the cache calling `get` or `peek`
+    /// without holding a borrow over it
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// for key in lfu.key_snapshot() {
+    ///     lfu.get(&key);
+    /// }
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    pub fn key_snapshot(&self) -> Vec<String> {
+        self.items.keys().cloned().collect()
+    }
+    ///
+    /// Every entry as (key, value, frequency) sorted by key, restorable with `insert_with_frequency`
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("b".to_string(), Bytes::from("2"));
+    /// lfu.insert("a".to_string(), Bytes::from("1"));
+    /// lfu.get("b");
+    /// assert_eq!(
+    ///     lfu.snapshot(),
+    ///     vec![("a".to_string(), Bytes::from("1"), 0), ("b".to_string(), Bytes::from("2"), 1)]
+    /// );
+    /// ```
+    pub fn snapshot(&self) -> Vec<(String, Bytes, usize)> {
+        let mut entries: Vec<(String, Bytes, usize)> = self
+            .items
+            .iter()
+            .map(|(key, item)| (key.clone(), self.decompress(item.data.clone()), item.parent))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+    ///
+    /// Copy of every entry ordered by key, values decompressed like `peek` returns them
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("b".to_string(), Bytes::from("2"));
+    /// lfu.insert("a".to_string(), Bytes::from("1"));
+    /// let keys: Vec<String> = lfu.to_btree_map().into_keys().collect();
+    /// assert_eq!(keys, vec!["a".to_string(), "b".to_string()]);
+    /// ```
+    pub fn to_btree_map(&self) -> BTreeMap<String, Bytes> {
+        self.items
+            .iter()
+            .map(|(key, item)| (key.clone(), self.decompress(item.data.clone())))
+            .collect()
+    }
+    ///
+    /// Compare with another cache key by key. Values are compared as stored, so caches with different
+    /// compression report every key as changed.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut a = LFU::new();
+    /// let mut b = LFU::new();
+    /// a.insert("x".to_string(), Bytes::from("1"));
+    /// b.insert("x".to_string(), Bytes::from("1"));
+    /// assert!(a.diff(&b).is_empty());
+    /// b.get("x");
+    /// assert_eq!(a.diff(&b).changed, vec!["x"]);
+    /// ```
+    pub fn diff<'a>(&'a self, other: &'a LFU) -> CacheDiff<'a> {
+        let mut diff = CacheDiff::default();
+        for (key, item) in &self.items {
+            match other.items.get(key) {
+                None => diff.only_in_self.push(key),
+                Some(theirs) if theirs.data != item.data || theirs.parent != item.parent => {
+                    diff.changed.push(key)
+                }
+                Some(_) => {}
+            }
+        }
+        diff.only_in_other = other
+            .items
+            .keys()
+            .filter(|key| !self.items.contains_key(*key))
+            .collect();
+        diff.only_in_self.sort_unstable();
+        diff.only_in_other.sort_unstable();
+        diff.changed.sort_unstable();
+        diff
+    }
+    ///
+    /// Keys holding exactly the given bytes, sorted. Values are compared decompressed.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("x"));
+    /// lfu.insert("b".to_string(), Bytes::from("y"));
+    /// assert_eq!(lfu.keys_with_value(&Bytes::from("x")), vec!["a"]);
+    /// ```
+    pub fn keys_with_value(&self, value: &Bytes) -> Vec<&String> {
+        let mut keys: Vec<&String> = self
+            .items
+            .iter()
+            .filter(|(_, item)| self.decompress(item.data.clone()) == *value)
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+    ///
+    /// Iterate over keys whose value matches a predicate, frequencies are left untouched.
+    /// Predicate sees decompressed values.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("v1:a"));
+    /// lfu.insert("b".to_string(), Bytes::from("v2:b"));
+    /// let keys: Vec<&String> = lfu.find(|value| value.starts_with(b"v1:")).collect();
+    /// assert_eq!(keys, vec!["a"]);
+    /// ```
+    pub fn find(&self, pred: impl Fn(&Bytes) -> bool) -> impl Iterator<Item = &String> {
+        self.items
+            .iter()
+            .filter(move |(_, item)| pred(&self.decompress(item.data.clone())))
+            .map(|(key, _)| key)
+    }
+
+    ///
+    /// Iterate over entries with mutable access to values, frequencies are left untouched.
+    /// Values are accounted by the size they were inserted with, so transformations changing value length
+    /// aren't reflected in current_size; re-insert such entries instead. With `compression` configured
+    /// values are exposed in compressed form.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// for (key, value) in lfu.iter_mut() {
+    ///     *value = Bytes::from(key.to_uppercase());
+    /// }
+    /// assert_eq!(lfu.get("a"), Some(Bytes::from("A")));
+    /// ```
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut Bytes)> {
+        self.items
+            .iter_mut()
+            .map(|(key, item)| (key, &mut item.data))
+    }
+
+    ///
+    /// Get a Some(value) or None for a given key. With `compression` configured the value is
+    /// decompressed, so it's always handed back owned.
+    ///
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.get("a"), None);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.get("a"), Some(Bytes::from("b")));
+    /// ```
+    pub fn get(&mut self, key: &str) -> Option<Bytes> {
+        self.get_with(key, true)
+    }
+    ///
+    /// Get a value, bumping its frequency like `get` only when `bump` is set, otherwise reading it like `peek`
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.get_with("a", false), Some(Bytes::from("b")));
+    /// assert_eq!(lfu.get_frequency("a"), 0);
+    /// assert_eq!(lfu.get_with("a", true), Some(Bytes::from("b")));
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    pub fn get_with(&mut self, key: &str, bump: bool) -> Option<Bytes> {
+        if !bump {
+            return self.peek(key);
+        }
+        let key = self.normalize(key);
+        if self.bump(&key).is_none() {
+            self.record_miss(&key);
+            return None;
+        }
+        self.items
+            .get(key.as_ref())
+            .map(|item| self.decompress(item.data.clone()))
+    }
+    ///
+    /// Remember a miss reported by the loader when negative caching is on. Expired misses are only
+    /// purged once the map reaches history_capacity, so misses are O(1) until then.
+    ///
+    fn add_tombstone(&mut self, key: &str) {
+        let ttl = match self.negative_ttl {
+            Some(ttl) => ttl,
+            None => return,
+        };
+        if self.tombstones.len() >= self.history_capacity {
+            self.tombstones
+                .retain(|_, cached_at| cached_at.elapsed() < ttl);
+        }
+        while !self.tombstones.is_empty() && self.tombstones.len() >= self.history_capacity {
+            let oldest = self
+                .tombstones
+                .iter()
+                .min_by_key(|(_, cached_at)| **cached_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.tombstones.remove(&oldest);
+            }
+        }
+        if self.history_capacity > 0 {
+            self.tombstones.insert(key.to_owned(), Instant::now());
+        }
+    }
+    ///
+    /// Count a miss on a key that was recently evicted as thrashing
+    ///
+    fn record_miss(&mut self, key: &str) {
+        if self.has_evicted_recently(key) {
+            self.thrash += 1;
+        }
+    }
+    ///
+    /// Read a value without bumping its frequency
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.peek("a"), Some(Bytes::from("b")));
+    /// assert_eq!(lfu.get_frequency("a"), 0);
+    /// ```
+    pub fn peek(&self, key: &str) -> Option<Bytes> {
+        self.items
+            .get(self.normalize(key).as_ref())
+            .map(|item| self.decompress(item.data.clone()))
+    }
+    ///
+    /// Read several values at once without bumping frequencies, results are aligned with `keys`
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.peek_many(&["a", "c"]), vec![Some(Bytes::from("b")), None]);
+    /// ```
+    pub fn peek_many(&self, keys: &[&str]) -> Vec<Option<Bytes>> {
+        keys.iter().map(|key| self.peek(key)).collect()
+    }
+    ///
+    /// Get a value together with its frequency after this access
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.get_with_frequency("a"), Some((Bytes::from("b"), 1)));
+    /// assert_eq!(lfu.get_with_frequency("a"), Some((Bytes::from("b"), 2)));
+    /// assert_eq!(lfu.get_with_frequency("c"), None);
+    /// ```
+    pub fn get_with_frequency(&mut self, key: &str) -> Option<(Bytes, usize)> {
+        let key = self.normalize(key);
+        let frequency = self.bump(&key)?;
+        self.items
+            .get(key.as_ref())
+            .map(|item| (self.decompress(item.data.clone()), frequency))
+    }
+    ///
+    /// Get value for a key bumping its frequency, or the provided default when it's missing.
+    /// Default isn't inserted into cache.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// let default = Bytes::from("default");
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.get_or("a", &default), Bytes::from("b"));
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// assert_eq!(lfu.get_or("c", &default), default);
+    /// assert_eq!(lfu.contains_key("c"), false);
+    /// ```
+    pub fn get_or(&mut self, key: &str, default: &Bytes) -> Bytes {
+        self.get(key).unwrap_or_else(|| default.clone())
+    }
+    ///
+    /// Increment frequency of a key moving it to the next frequency node, returns the new frequency.
+    /// Frequency saturates at `max_frequency_cap`, top of `max_levels` or usize::MAX, whichever is lowest;
+    /// keys that reached it stay where they are instead of wrapping around.
+    ///
+    fn bump(&mut self, key: &str) -> Option<usize> {
+        let cap = self
+            .max_frequency
+            .unwrap_or(usize::MAX)
+            .min(self.top_level());
+        let item = self.items.get_mut(key)?;
+        item.last_access = Instant::now();
+        let parent = item.parent;
+        if parent >= cap {
+            return Some(parent);
+        }
+        let frequency = parent.saturating_add(1);
+        self.set_frequency(key, frequency);
+        Some(frequency)
+    }
+    ///
+    /// Set frequency of a key to an arbitrary value moving it to matching frequency node,
+    /// returns whether the key exists
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.set_frequency("a", 3), false);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.set_frequency("a", 3), true);
+    /// assert_eq!(lfu.get_frequency("a"), 3);
+    /// ```
+    pub fn set_frequency(&mut self, key: &str, frequency: usize) -> bool {
+        let key = self.normalize(key);
+        let key = key.as_ref();
+        let frequency = frequency.min(self.top_level());
+        let item = match self.items.get_mut(key) {
+            Some(item) => item,
+            None => return false,
+        };
+        let previous = std::mem::replace(&mut item.parent, frequency);
+        if previous == frequency {
+            return true;
+        }
+        let seq = item.seq;
+        if let Some(frequency_node) = self.frequency_list.get_mut(previous) {
+            frequency_node.items.retain(|x| x != key);
+        }
+        FrequencyNode::at(&mut self.frequency_list, frequency).insert_key(
+            key.to_owned(),
+            seq,
+            &self.items,
+        );
+        true
+    }
+    ///
+    /// Make a key the hottest one by moving it one frequency above the highest currently held by any key,
+    /// returns whether the key exists. Frequency still saturates at `max_frequency_cap` and `max_levels`.
+    /// Unlike `pin` the key stays evictable, it's just the last candidate.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.set_frequency("a", 4);
+    /// assert_eq!(lfu.promote_to_top("c"), true);
+    /// assert_eq!(lfu.get_frequency("c"), 5);
+    /// ```
+    pub fn promote_to_top(&mut self, key: &str) -> bool {
+        if !self.contains_key(key) {
+            return false;
+        }
+        let hottest = self
+            .frequency_list
+            .iter()
+            .rposition(|frequency_node| !frequency_node.items.is_empty())
+            .unwrap_or(0);
+        let frequency = hottest
+            .saturating_add(1)
+            .min(self.max_frequency.unwrap_or(usize::MAX));
+        self.set_frequency(key, frequency)
+    }
+    ///
+    /// When a key was last read by `get` and friends, or inserted if it wasn't read since.
+    /// `peek` doesn't count as access.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// use std::time::Instant;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert!(lfu.last_access("a").unwrap() <= Instant::now());
+    /// assert_eq!(lfu.last_access("c"), None);
+    /// ```
+    pub fn last_access(&self, key: &str) -> Option<Instant> {
+        self.items
+            .get(self.normalize(key).as_ref())
+            .map(|item| item.last_access)
+    }
+    ///
+    /// Decrement frequency of a key by one moving it to the lower frequency node, returns whether the key exists.
+    /// Inverse of the bump done by `get`, keys at frequency 0 stay there.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.demote("a"), true);
+    /// assert_eq!(lfu.get_frequency("a"), 0);
+    /// assert_eq!(lfu.demote("c"), false);
+    /// ```
+    pub fn demote(&mut self, key: &str) -> bool {
+        let key = self.normalize(key);
+        let parent = match self.items.get(key.as_ref()) {
+            Some(item) => item.parent,
+            None => return false,
+        };
+        self.set_frequency(&key, parent.saturating_sub(1))
+    }
+    ///
+    /// Get a value for a given key, calling `loader` to fetch and insert it on a miss.
+    /// With `negative_cache` enabled a `None` from the loader is remembered, so further calls
+    /// within the ttl return None without invoking the loader.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.get_or_load("a", |_| Some(Bytes::from("b"))), Some(Bytes::from("b")));
+    /// assert_eq!(lfu.get_or_load("a", |_| None), Some(Bytes::from("b")));
+    /// ```
+    pub fn get_or_load<F>(&mut self, key: &str, loader: F) -> Option<Bytes>
+    where
+        F: FnOnce(&str) -> Option<Bytes>,
+    {
+        let key = self.normalize(key);
+        let key = key.as_ref();
+        if self.contains_key(key) {
+            return self.get(key);
+        }
+        self.record_miss(key);
+        if let Some(cached_at) = self.tombstones.get(key) {
+            match self.negative_ttl {
+                Some(ttl) if cached_at.elapsed() < ttl => return None,
+                _ => {
+                    self.tombstones.remove(key);
+                }
+            }
+        }
+        match loader(key) {
+            Some(value) => {
+                self.insert(key.to_owned(), value);
+                self.peek(key)
+            }
+            None => {
+                self.add_tombstone(key);
+                None
+            }
+        }
+    }
+    ///
+    /// Get a value bumping its frequency, or insert the one computed by `f` on a miss. Flag tells
+    /// whether `f` ran, i.e. whether this was a miss. A computed value over `max_value_size` is
+    /// reported as an error like `try_insert` does, and isn't cached.
+    ///
+    /// ```
+    /// use lfu_vecs::{InsertError, LFU};
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_value_size(4);
+    /// assert_eq!(
+    ///     lfu.get_or_insert_with_status("a".to_string(), || Bytes::from("b")),
+    ///     Ok((Bytes::from("b"), true))
+    /// );
+    /// assert_eq!(
+    ///     lfu.get_or_insert_with_status("c".to_string(), || Bytes::from("too long")),
+    ///     Err(InsertError::ValueTooLarge { size: 8, limit: 4 })
+    /// );
+    /// ```
+    pub fn get_or_insert_with_status(
+        &mut self,
+        key: String,
+        f: impl FnOnce() -> Bytes,
+    ) -> Result<(Bytes, bool), InsertError> {
+        let key = self.normalize_owned(key);
+        let inserted = self.bump(&key).is_none();
+        if inserted {
+            self.record_miss(&key);
+            self.try_insert(key.clone(), f())?;
+        }
+        let value = self.decompress(self.items[&key].data.clone());
+        Ok((value, inserted))
+    }
+    ///
+    /// Record evicted key in history
+    ///
+    fn add_to_history(&mut self, dropped_key: String, frequency: usize) {
+        if self.history_capacity == 0 {
+            return;
+        }
+        while self.history.len() >= self.history_capacity {
+            self.history.pop_back();
+        }
+        self.history.push_front(Eviction {
+            key: dropped_key,
+            frequency,
+            evicted_at: Instant::now(),
+        });
+    }
+    ///
+    /// Check if key was recently dropped from cache. History remembers last `history_capacity` evicted keys
+    ///
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(3);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// lfu.insert("c".to_string(), Bytes::from("43"));
+    /// assert_eq!(lfu.has_evicted_recently("a"), true);
+    /// ```
+
+    pub fn has_evicted_recently(&self, key: &str) -> bool {
+        let key = self.normalize(key);
+        self.history.iter().any(|eviction| eviction.key == key)
+    }
+    ///
+    /// Frequency a key had when it was evicted, if it's still remembered in history
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(3);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.get("a");
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// assert_eq!(lfu.evicted_frequency("a"), Some(1));
+    /// assert_eq!(lfu.evicted_frequency("b"), None);
+    /// ```
+    pub fn evicted_frequency(&self, key: &str) -> Option<usize> {
+        let key = self.normalize(key);
+        self.history
+            .iter()
+            .find(|eviction| eviction.key == key)
+            .map(|eviction| eviction.frequency)
+    }
+    ///
+    /// Iterate over recently evicted keys together with time of eviction, most recent first
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(3);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// let keys: Vec<&String> = lfu.eviction_history().map(|(key, _)| key).collect();
+    /// assert_eq!(keys, vec!["a"]);
+    /// ```
+    pub fn eviction_history(&self) -> impl Iterator<Item = (&String, Instant)> {
+        self.history
+            .iter()
+            .map(|eviction| (&eviction.key, eviction.evicted_at))
+    }
+    ///
+    /// Number of evicted keys history currently remembers, never more than `history_capacity`
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(3).history_capacity(2);
+    /// assert_eq!(lfu.history_len(), 0);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// assert_eq!(lfu.history_len(), 1);
+    /// for key in &["c", "d", "e"] {
+    ///     lfu.insert(key.to_string(), Bytes::from("44"));
+    /// }
+    /// assert_eq!(lfu.history_len(), 2);
+    /// ```
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+    ///
+    /// Forget every evicted key while keeping cached entries and their frequencies
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(3);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// lfu.clear_history();
+    /// assert_eq!(lfu.history_len(), 0);
+    /// ```
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+    ///
+    /// Check if key is either cached right now or was recently evicted
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(3);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// assert_eq!(lfu.seen_recently("a"), true);
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// assert_eq!(lfu.contains_key("a"), false);
+    /// assert_eq!(lfu.seen_recently("a"), true);
+    /// assert_eq!(lfu.seen_recently("c"), false);
+    /// ```
+    pub fn seen_recently(&self, key: &str) -> bool {
+        self.contains_key(key) || self.has_evicted_recently(key)
+    }
+
+    ///
+    /// Move entry stored under `old` to `new` keeping its value and frequency, returns whether `old` existed.
+    /// If `new` is already cached its entry is overwritten and dropped, without going to history.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("A".to_string(), Bytes::from("42"));
+    /// assert_eq!(lfu.rename("A", "a".to_string()), true);
+    /// assert_eq!(lfu.rename("A", "a".to_string()), false);
+    /// assert_eq!(lfu.get("a"), Some(Bytes::from("42")));
+    /// ```
+    pub fn rename(&mut self, old: &str, new: String) -> bool {
+        let old = self.normalize(old);
+        let old = old.as_ref();
+        let new = self.normalize_owned(new);
+        if old == new {
+            return self.items.contains_key(old);
+        }
+        let item = match self.items.remove(old) {
+            Some(item) => item,
+            None => return false,
+        };
+        self.remove_item(&new);
+        if let Some(frequency_node) = self.frequency_list.get_mut(item.parent) {
+            frequency_node.items.retain(|x| x != old);
+        }
+        FrequencyNode::at(&mut self.frequency_list, item.parent).insert_key(
+            new.clone(),
+            item.seq,
+            &self.items,
+        );
+        self.items.insert(new, item);
+        true
+    }
+
+    ///
+    /// Insert a value into LFU
+    ///
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("a".to_string(), Bytes::from("z"));
+    /// assert_eq!(lfu.get("a"), Some(Bytes::from("z")));
+    /// ```
+    pub fn insert(&mut self, key: String, value: Bytes) -> Option<Bytes> {
+        self.insert_evicting(key, value).0
+    }
+    ///
+    /// Insert a value like `insert`, reporting values over `max_value_size` as an error instead of
+    /// silently refusing them
+    ///
+    /// ```
+    /// use lfu_vecs::{InsertError, LFU};
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_value_size(2);
+    /// assert_eq!(lfu.try_insert("a".to_string(), Bytes::from("b")), Ok(None));
+    /// assert_eq!(
+    ///     lfu.try_insert("a".to_string(), Bytes::from("bcd")),
+    ///     Err(InsertError::ValueTooLarge { size: 3, limit: 2 })
+    /// );
+    /// ```
+    pub fn try_insert(&mut self, key: String, value: Bytes) -> Result<Option<Bytes>, InsertError> {
+        self.check_value_size(value.len())?;
+        Ok(self.insert(key, value))
+    }
+    ///
+    /// Insert a value charged `cost` against max_size instead of its length, e.g. to make small but
+    /// expensive to recompute entries heavier. current_size then tracks summed costs; a plain `insert`
+    /// over the key goes back to charging its length.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert_with_cost("a".to_string(), Bytes::from("b"), 10);
+    /// assert_eq!(lfu.current_size(), 10);
+    /// ```
+    pub fn insert_with_cost(&mut self, key: String, value: Bytes, cost: usize) -> Option<Bytes> {
+        self.insert_charged(key, value, Some(cost)).0
+    }
+    ///
+    /// Insert a value for a key that isn't cached yet, failing with `InsertError::KeyExists` and leaving
+    /// the cache untouched otherwise. Values over `max_value_size` are reported like `try_insert` does.
+    ///
+    /// ```
+    /// use lfu_vecs::{InsertError, LFU};
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.insert_new("a".to_string(), Bytes::from("b")), Ok(()));
+    /// assert_eq!(
+    ///     lfu.insert_new("a".to_string(), Bytes::from("c")),
+    ///     Err(InsertError::KeyExists)
+    /// );
+    /// assert_eq!(lfu.get("a"), Some(Bytes::from("b")));
+    /// ```
+    pub fn insert_new(&mut self, key: String, value: Bytes) -> Result<(), InsertError> {
+        if self.contains_key(&key) {
+            return Err(InsertError::KeyExists);
+        }
+        self.try_insert(key, value)?;
+        Ok(())
+    }
+    ///
+    /// Insert returning previous value together with frequency of the key afterwards,
+    /// `initial_frequency` unless `preserve_frequency_on_overwrite` kept frequency of an overwritten entry
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.insert_tracked("a".to_string(), Bytes::from("b")), (None, 0));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.insert_tracked("a".to_string(), Bytes::from("c")), (Some(Bytes::from("b")), 0));
+    /// ```
+    pub fn insert_tracked(&mut self, key: String, value: Bytes) -> (Option<Bytes>, usize) {
+        let key = self.normalize_owned(key);
+        let (previous, _) = self.insert_evicting(key.clone(), value);
+        let frequency = self.items.get(&key).map_or(0, |item| item.parent);
+        (previous, frequency)
+    }
+    ///
+    /// Keys that inserting a new value of `incoming_size` bytes would evict, cache is left untouched.
+    /// With the default policy, among keys of the same frequency the most recently inserted one goes first,
+    /// so eviction depends only on the sequence of calls made, never on HashMap iteration order.
+    /// Randomized policies preview exactly what the next insert would do.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(5);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// assert_eq!(lfu.would_evict(2), vec!["b".to_string()]);
+    /// assert_eq!(lfu.current_size(), 4);
+    /// ```
+    pub fn would_evict(&self, incoming_size: usize) -> Vec<String> {
+        let mut rng = self.rng;
+        self.select_victims(self.charge(incoming_size), &mut rng)
+    }
+    ///
+    /// Keys to evict so that `incoming_size` bytes fit, according to eviction policy.
+    /// Randomized policies draw from `rng`, cache is left untouched.
+    ///
+    fn select_victims(&self, incoming_size: usize, rng: &mut Rng) -> Vec<String> {
+        if self.eviction_policy == EvictionPolicy::WeightedRandom {
+            return self.weighted_random_victims(incoming_size, rng);
+        }
+        let mut evicted = vec![];
+        let mut size = self.current_size;
+        let mut count = self.items.len();
+        let max_evictions = self.max_evictions.unwrap_or(usize::MAX);
+        let mut candidates = self
+            .frequency_list
+            .iter()
+            .flat_map(|frequency_node| self.node_victims(frequency_node));
+        while (size + incoming_size >= self.max_size || self.at_entry_limit(count))
+            && evicted.len() < max_evictions
+        {
+            match candidates.next() {
+                Some((key, item)) => {
+                    size -= item.size;
+                    count -= 1;
+                    evicted.push(key.clone());
+                }
+                None => break,
+            }
+        }
+        evicted
+    }
+    ///
+    /// Victims for `EvictionPolicy::WeightedRandom`: every evictable key is a candidate with weight
+    /// 1 / (frequency + 1)
+    ///
+    fn weighted_random_victims(&self, incoming_size: usize, rng: &mut Rng) -> Vec<String> {
+        let weight = |item: &Item| 1.0 / (item.parent as f64 + 1.0);
+        let mut candidates: Vec<(&String, &Item)> = self
+            .frequency_list
+            .iter()
+            .flat_map(|frequency_node| self.node_victims(frequency_node))
+            .collect();
+        let mut evicted = vec![];
+        let mut size = self.current_size;
+        let mut count = self.items.len();
+        let max_evictions = self.max_evictions.unwrap_or(usize::MAX);
+        while (size + incoming_size >= self.max_size || self.at_entry_limit(count))
+            && evicted.len() < max_evictions
+            && !candidates.is_empty()
+        {
+            let total: f64 = candidates.iter().map(|(_, item)| weight(item)).sum();
+            let mut target = rng.next_f64() * total;
+            let index = candidates
+                .iter()
+                .position(|(_, item)| {
+                    target -= weight(item);
+                    target < 0.0
+                })
+                .unwrap_or(candidates.len() - 1);
+            let (key, item) = candidates.swap_remove(index);
+            size -= item.size;
+            count -= 1;
+            evicted.push(key.clone());
+        }
+        evicted
+    }
+    ///
+    /// Cheap check whether inserting a new value of `incoming_size` bytes would evict anything
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(5);
+    /// assert_eq!(lfu.would_evict_any(2), false);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// assert_eq!(lfu.would_evict_any(2), true);
+    /// ```
+    pub fn would_evict_any(&self, incoming_size: usize) -> bool {
+        self.current_size + self.charge(incoming_size) >= self.max_size
+            || self.at_entry_limit(self.items.len())
+    }
+    ///
+    /// Keys to drop, coldest first, to free at least `bytes`. Pinned keys are never picked
+    /// and cache is left untouched. If the whole cache is smaller, every evictable key is returned.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.victims_to_free(1), vec!["b".to_string()]);
+    /// assert_eq!(lfu.victims_to_free(3), vec!["b".to_string(), "a".to_string()]);
+    /// ```
+    pub fn victims_to_free(&self, bytes: usize) -> Vec<String> {
+        let mut victims = vec![];
+        let mut freed = 0;
+        let candidates = self
+            .frequency_list
+            .iter()
+            .flat_map(|frequency_node| self.node_victims(frequency_node));
+        for (key, item) in candidates {
+            if freed >= bytes {
+                break;
+            }
+            freed += item.size;
+            victims.push(key.clone());
+        }
+        victims
+    }
+    ///
+    /// Every evictable key in the order eviction would remove them, coldest frequency node first.
+    /// Pinned keys are left out.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// lfu.insert("c".to_string(), Bytes::from("44"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.eviction_order(), vec!["c", "b", "a"]);
+    /// ```
+    pub fn eviction_order(&self) -> Vec<String> {
+        self.frequency_list
+            .iter()
+            .flat_map(|frequency_node| self.node_victims(frequency_node))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+    ///
+    /// Lazily remove and yield entries coldest first, in eviction order, e.g. to spill them to another tier.
+    /// Only entries actually consumed are removed; pinned ones are never yielded. Removed keys don't go to history.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.drain_coldest().next(), Some(("b".to_string(), Bytes::from("43"))));
+    /// assert_eq!(lfu.len(), 1);
+    /// ```
+    pub fn drain_coldest(&mut self) -> impl Iterator<Item = (String, Bytes)> + '_ {
+        std::iter::from_fn(move || {
+            let key = self.peek_lfu()?.clone();
+            let item = self.remove_item(&key)?;
+            Some((key, self.decompress(item.data)))
+        })
+    }
+    ///
+    /// Check if a key sits in the coldest frequency node holding evictable keys, i.e. it's among
+    /// the next ones in line for eviction. Pinned keys are never candidates.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.is_eviction_candidate("b"), true);
+    /// assert_eq!(lfu.is_eviction_candidate("a"), false);
+    /// ```
+    pub fn is_eviction_candidate(&self, key: &str) -> bool {
+        let item = match self.items.get(self.normalize(key).as_ref()) {
+            Some(item) if !item.pinned => item,
+            _ => return false,
+        };
+        self.frequency_list
+            .iter()
+            .position(|frequency_node| self.node_victims(frequency_node).next().is_some())
+            == Some(item.parent)
+    }
+    ///
+    /// Next key eviction would remove, None when there's nothing evictable
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.peek_lfu(), None);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// assert_eq!(lfu.peek_lfu(), Some(&"a".to_string()));
+    /// ```
+    pub fn peek_lfu(&self) -> Option<&String> {
+        self.frequency_list
+            .iter()
+            .find_map(|frequency_node| self.node_victims(frequency_node).next())
+            .map(|(key, _)| key)
+    }
+    ///
+    /// Evict the key `peek_lfu` points at, returning it with its value. Goes to history like any eviction.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// assert_eq!(lfu.pop_lfu(), Some(("a".to_string(), Bytes::from("42"))));
+    /// assert_eq!(lfu.has_evicted_recently("a"), true);
+    /// ```
+    pub fn pop_lfu(&mut self) -> Option<(String, Bytes)> {
+        let key = self.peek_lfu()?.clone();
+        let data = self.evict(key.clone())?;
+        Some((key, self.decompress(data)))
+    }
+    ///
+    /// Part of max_size inserting `value` under `key` would take, applying every configured sizing rule:
+    /// compression, charging empty values a byte and `count_key_bytes`. Compression is actually run
+    /// on the value to measure it.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let lfu = LFU::new().count_key_bytes(true);
+    /// assert_eq!(lfu.effective_size("ab", &Bytes::from("cde")), 5);
+    /// ```
+    pub fn effective_size(&self, key: &str, value: &Bytes) -> usize {
+        let value_len = match &self.compressor {
+            Some(compressor) => compressor.compress(value).len(),
+            None => value.len(),
+        };
+        self.charge_entry(&self.normalize(key), value_len)
+    }
+    ///
+    /// Insert a value starting at given frequency instead of 0, e.g. when restoring a `snapshot`
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert_with_frequency("a".to_string(), Bytes::from("b"), 5);
+    /// assert_eq!(lfu.get_frequency("a"), 5);
+    /// ```
+    pub fn insert_with_frequency(
+        &mut self,
+        key: String,
+        value: Bytes,
+        frequency: usize,
+    ) -> Option<Bytes> {
+        let previous = self.insert(key.clone(), value);
+        self.set_frequency(&key, frequency);
+        previous
+    }
+    ///
+    /// Remove a key from cache, returns whether it was cached. Removed keys don't go to history.
+    /// Also cancels a reservation made by `reserve_slot`.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.remove("a"), true);
+    /// assert_eq!(lfu.remove("a"), false);
+    /// assert_eq!(lfu.current_size(), 0);
+    /// ```
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.remove_item(key).is_some() || self.release_reservation(key)
+    }
+    ///
+    /// Reserve `size` bytes for a value that's still being computed, evicting to make room like `insert`
+    /// would. Reservation counts towards current_size until `fulfill_slot` replaces it with the value
+    /// or `remove` cancels it. Returns false without reserving when the key is already cached or reserved.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.reserve_slot("a".to_string(), 4), true);
+    /// assert_eq!(lfu.current_size(), 4);
+    /// assert_eq!(lfu.contains_key("a"), false);
+    /// assert_eq!(lfu.fulfill_slot("a", Bytes::from("bc")), true);
+    /// assert_eq!(lfu.current_size(), 2);
+    /// ```
+    pub fn reserve_slot(&mut self, key: String, size: usize) -> bool {
+        let key = self.normalize_owned(key);
+        if self.items.contains_key(&key) || self.reservations.contains_key(&key) {
+            return false;
+        }
+        let size = self.charge(size);
+        self.signal_full(size);
+        self.evict_to_fit(size);
+        self.current_size += size;
+        self.reservations.insert(key, size);
+        true
+    }
+    ///
+    /// Replace a reservation made by `reserve_slot` with the actual value, sized by its own length.
+    /// Returns false and inserts nothing when there's no reservation for the key, or when the value is
+    /// over `max_value_size`, in which case the reservation stays until `remove` cancels it.
+    ///
+    pub fn fulfill_slot(&mut self, key: &str, value: Bytes) -> bool {
+        if self.check_value_size(value.len()).is_err() || !self.release_reservation(key) {
+            return false;
+        }
+        self.insert(key.to_owned(), value);
+        true
+    }
+    ///
+    /// Remove a key and hand back its value, frequency isn't bumped
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.take("a"), Some(Bytes::from("b")));
+    /// assert_eq!(lfu.take("a"), None);
+    /// ```
+    pub fn take(&mut self, key: &str) -> Option<Bytes> {
+        self.remove_item(key).map(|item| self.decompress(item.data))
+    }
+    ///
+    /// Remove every given key, returns how many of them were actually cached
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.remove_many(&["a", "c"]), 1);
+    /// ```
+    pub fn remove_many(&mut self, keys: &[&str]) -> usize {
+        keys.iter().filter(|key| self.remove(key)).count()
+    }
+    ///
+    /// Evict every entry with frequency below `min_frequency` regardless of size pressure, returns how many were
+    /// evicted. Evicted keys go to history like size driven evictions, pinned keys are kept.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.evict_below(1), 1);
+    /// assert_eq!(lfu.has_evicted_recently("c"), true);
+    /// assert_eq!(lfu.contains_key("a"), true);
+    /// ```
+    pub fn evict_below(&mut self, min_frequency: usize) -> usize {
+        let keys: Vec<String> = self
+            .frequency_list
+            .iter()
+            .take(min_frequency)
+            .flat_map(|frequency_node| self.node_victims(frequency_node))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.into_iter().filter_map(|key| self.evict(key)).count()
+    }
+    ///
+    /// Evict every entry not read (see `last_access`) within `age`, returns how many were evicted.
+    /// A recency floor on top of frequency based eviction; evicted keys go to history, pinned keys are kept.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// use std::time::Duration;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.purge_older_than(Duration::from_secs(60)), 0);
+    /// assert_eq!(lfu.purge_older_than(Duration::from_secs(0)), 1);
+    /// ```
+    pub fn purge_older_than(&mut self, age: Duration) -> usize {
+        let keys: Vec<String> = self
+            .frequency_list
+            .iter()
+            .flat_map(|frequency_node| self.node_victims(frequency_node))
+            .filter(|(_, item)| item.last_access.elapsed() >= age)
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.into_iter().filter_map(|key| self.evict(key)).count()
+    }
+    ///
+    /// Move every entry whose key matches `pred` into a new cache, keeping their frequencies.
+    /// New cache inherits max_size, history, retention, frequency and case sensitivity settings
+    /// but not compression, so moved values are stored decompressed.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("user:1".to_string(), Bytes::from("a"));
+    /// lfu.insert("post:1".to_string(), Bytes::from("b"));
+    /// let users = lfu.split_off(|key| key.starts_with("user:"));
+    /// assert_eq!(users.contains_key("user:1"), true);
+    /// assert_eq!(lfu.contains_key("user:1"), false);
+    /// ```
+    pub fn split_off(&mut self, pred: impl Fn(&str) -> bool) -> LFU {
+        let mut other = LFU::new().max_size(self.max_size);
+        other.history_capacity = self.history_capacity;
+        other.negative_ttl = self.negative_ttl;
+        other.max_frequency = self.max_frequency;
+        other.max_levels = self.max_levels;
+        other.preserve_frequency = self.preserve_frequency;
+        other.initial_frequency = self.initial_frequency;
+        other.max_value_size = self.max_value_size;
+        other.count_key_bytes = self.count_key_bytes;
+        other.max_evictions = self.max_evictions;
+        other.max_entries = self.max_entries;
+        other.eviction_policy = self.eviction_policy;
+        other.retained_capacity = self.retained_capacity;
+        other.case_insensitive = self.case_insensitive;
+        let keys: Vec<String> = self.items.keys().filter(|key| pred(key)).cloned().collect();
+        for key in keys {
+            if let Some(mut item) = self.remove_item(&key) {
+                item.data = self.decompress(item.data);
+                item.size = other.charge_entry(&key, item.data.len());
+                other.attach_item(key, item);
+            }
+        }
+        other
+    }
+    ///
+    /// Verify that every cached key is listed exactly once, in the frequency node matching its frequency,
+    /// and that current_size equals the sum of sizes charged for cached values. Meant for tests and debugging,
+    /// it walks the whole cache.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.check_integrity(), Ok(()));
+    /// ```
+    pub fn check_integrity(&self) -> Result<(), IntegrityError> {
+        let mut seen: HashMap<&str, usize> = HashMap::with_capacity(self.items.len());
+        for (level, frequency_node) in self.frequency_list.iter().enumerate() {
+            for key in &frequency_node.items {
+                let item = match self.items.get(key) {
+                    Some(item) => item,
+                    None => {
+                        return Err(IntegrityError::StaleKey {
+                            key: key.clone(),
+                            level,
+                        })
+                    }
+                };
+                if seen.insert(key, level).is_some() {
+                    return Err(IntegrityError::DuplicateKey { key: key.clone() });
+                }
+                if item.parent != level {
+                    return Err(IntegrityError::WrongLevel {
+                        key: key.clone(),
+                        frequency: item.parent,
+                        level,
+                    });
+                }
+            }
+        }
+        if let Some(key) = self
+            .items
+            .keys()
+            .find(|key| !seen.contains_key(key.as_str()))
+        {
+            return Err(IntegrityError::MissingKey { key: key.clone() });
+        }
+        let actual: usize = self.items.values().map(|item| item.size).sum::<usize>()
+            + self.reservations.values().sum::<usize>();
+        if actual != self.current_size {
+            return Err(IntegrityError::SizeMismatch {
+                tracked: self.current_size,
+                actual,
+            });
+        }
+        Ok(())
+    }
+    ///
+    /// Split memory used by the cache into payload and structural overhead. Sizes of allocations are
+    /// estimated from lengths and capacities, allocator overhead isn't included. With default sizing
+    /// and no empty values `value_bytes` equals `current_size`.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("ab".to_string(), Bytes::from("cde"));
+    /// let breakdown = lfu.size_breakdown();
+    /// assert_eq!(breakdown.value_bytes, 3);
+    /// assert_eq!(breakdown.key_bytes, 4);
+    /// ```
+    pub fn size_breakdown(&self) -> SizeBreakdown {
+        let key_bytes: usize = self.items.keys().map(String::len).sum();
+        let frequency_list_bytes = self.frequency_list.capacity()
+            * std::mem::size_of::<FrequencyNode>()
+            + self
+                .frequency_list
+                .iter()
+                .map(|frequency_node| {
+                    frequency_node.items.capacity() * std::mem::size_of::<String>()
+                })
+                .sum::<usize>();
+        let history_bytes = self.history.capacity() * std::mem::size_of::<Eviction>()
+            + self
+                .history
+                .iter()
+                .map(|eviction| eviction.key.len())
+                .sum::<usize>();
+        let tombstone_bytes = self.tombstones.capacity() * std::mem::size_of::<(String, Instant)>()
+            + self.tombstones.keys().map(String::len).sum::<usize>();
+        SizeBreakdown {
+            value_bytes: self.items.values().map(|item| item.data.len()).sum(),
+            key_bytes: key_bytes * 2,
+            frequency_list_bytes,
+            history_bytes,
+            tombstone_bytes,
+        }
+    }
+    ///
+    /// Number of frequency nodes currently allocated, including empty ones
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// let lfu = LFU::new().prealloc_levels(8);
+    /// assert_eq!(lfu.frequency_levels(), 8);
+    /// ```
+    pub fn frequency_levels(&self) -> usize {
+        self.frequency_list.len()
+    }
+    ///
+    /// Number of entries never read since insert (frequency 0). A high share of these points to
+    /// scan-heavy traffic polluting the cache.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.cold_count(), 1);
+    /// lfu.get("a");
+    /// assert_eq!(lfu.cold_count(), 0);
+    /// ```
+    pub fn cold_count(&self) -> usize {
+        self.level_keys(0).len()
+    }
+    ///
+    /// Keys still at frequency 0 that were inserted at least `min_age` ago, sorted. These are entries
+    /// nobody read since insert, i.e. dead weight.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// use std::time::Duration;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.stale_cold_keys(Duration::from_secs(0)), vec!["a"]);
+    /// assert!(lfu.stale_cold_keys(Duration::from_secs(60)).is_empty());
+    /// ```
+    pub fn stale_cold_keys(&self, min_age: Duration) -> Vec<&String> {
+        let mut keys: Vec<&String> = self
+            .level_keys(0)
+            .iter()
+            .filter(|key| {
+                self.items
+                    .get(*key)
+                    .is_some_and(|item| item.inserted_at.elapsed() >= min_age)
+            })
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+    ///
+    /// Number of keys with frequency at or below `level`, e.g. to size a decay or eviction pass
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.keys_at_or_below(0), 1);
+    /// assert_eq!(lfu.keys_at_or_below(1), 2);
+    /// ```
+    pub fn keys_at_or_below(&self, level: usize) -> usize {
+        self.frequency_list
+            .iter()
+            .take(level.saturating_add(1))
+            .map(|frequency_node| frequency_node.items.len())
+            .sum()
+    }
+    ///
+    /// Key with the highest frequency together with that frequency, None for empty cache.
+    /// Among equally hot keys the one inserted earliest wins.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.hottest_key(), None);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.hottest_key(), Some((&"a".to_string(), 1)));
+    /// ```
+    pub fn hottest_key(&self) -> Option<(&String, usize)> {
+        let (level, frequency_node) = self
+            .frequency_list
+            .iter()
+            .enumerate()
+            .rfind(|(_, frequency_node)| !frequency_node.items.is_empty())?;
+        frequency_node.items.first().map(|key| (key, level))
+    }
+    ///
+    /// Number of frequency nodes holding at least one key. A big gap to `frequency_levels` means
+    /// frequency_list is fragmented and `compact` may be worth calling.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.set_frequency("a", 4);
+    /// assert_eq!(lfu.populated_levels(), 1);
+    /// assert_eq!(lfu.frequency_levels(), 5);
+    /// ```
+    pub fn populated_levels(&self) -> usize {
+        self.frequency_list
+            .iter()
+            .filter(|frequency_node| !frequency_node.items.is_empty())
+            .count()
+    }
+    ///
+    /// Largest number of frequency nodes this cache ever had allocated, even if `compact` or `clear`
+    /// shrunk frequency_list since. Shows whether compaction actually recovers memory.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// let mut lfu = LFU::new().prealloc_levels(8);
+    /// lfu.compact();
+    /// assert_eq!(lfu.frequency_levels(), 1);
+    /// assert_eq!(lfu.peak_levels(), 8);
+    /// ```
+    pub fn peak_levels(&self) -> usize {
+        self.max_levels_seen.max(self.frequency_list.len())
+    }
+    ///
+    /// Drop trailing empty frequency nodes left behind by removals and evictions
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().prealloc_levels(64);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.compact();
+    /// lfu.get("a");
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    pub fn compact(&mut self) {
+        self.note_levels();
+        while self.frequency_list.len() > 1
+            && self
+                .frequency_list
+                .last()
+                .is_some_and(|frequency_node| frequency_node.items.is_empty())
+        {
+            self.frequency_list.pop();
+        }
+        self.frequency_list.shrink_to_fit();
+    }
+    ///
+    /// Drop every cached entry and remembered miss, configuration and history of evicted keys are kept
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.clear();
+    /// assert_eq!(lfu.contains_key("a"), false);
+    /// assert_eq!(lfu.current_size(), 0);
+    /// ```
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.reservations.clear();
+        self.tombstones.clear();
+        self.note_levels();
+        self.frequency_list.clear();
+        self.frequency_list.push(FrequencyNode::new());
+        self.current_size = 0;
+        self.full = false;
+    }
+    ///
+    /// Insert a versioned value unless cached one has the same or newer version, returns whether it
+    /// was inserted. Winning overwrite keeps frequency of the entry it replaces. Plain `insert` resets version to 0.
+    /// Values over `max_value_size` are refused and leave the cached entry and its version untouched.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.insert_if_newer("a".to_string(), Bytes::from("v2"), 2), true);
+    /// assert_eq!(lfu.insert_if_newer("a".to_string(), Bytes::from("v1"), 1), false);
+    /// assert_eq!(lfu.get("a"), Some(Bytes::from("v2")));
+    /// ```
+    pub fn insert_if_newer(&mut self, key: String, value: Bytes, version: u64) -> bool {
+        if self.check_value_size(value.len()).is_err() {
+            return false;
+        }
+        let key = self.normalize_owned(key);
+        let frequency = match self.items.get(&key) {
+            Some(item) if item.version >= version => return false,
+            Some(item) => item.parent,
+            None => self.initial_frequency.min(self.top_level()),
+        };
+        self.insert_with_frequency(key.clone(), value, frequency);
+        if let Some(item) = self.items.get_mut(&key) {
+            item.version = version;
+        }
+        true
+    }
+    ///
+    /// Insert a value assembled from several chunks, sized by their total length
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert_from_chunks("a".to_string(), vec![Bytes::from("4"), Bytes::from("2")]);
+    /// assert_eq!(lfu.get("a"), Some(Bytes::from("42")));
+    /// ```
+    pub fn insert_from_chunks(
+        &mut self,
+        key: String,
+        chunks: impl IntoIterator<Item = Bytes>,
+    ) -> Option<Bytes> {
+        let mut value = BytesMut::new();
+        for chunk in chunks {
+            value.extend_from_slice(&chunk);
+        }
+        self.insert(key, value.freeze())
+    }
+    ///
+    /// Append bytes to an existing value keeping its frequency and pin, returns whether the value was
+    /// extended. Missing keys and appends that would go over max_value_size are left alone.
+    /// current_size grows by the appended length, evicting other entries if it no longer fits, and
+    /// on_insert and on_full fire as for `insert`. Compressed values are decompressed and compressed again.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("4"));
+    /// assert_eq!(lfu.append("a", b"2"), true);
+    /// assert_eq!(lfu.get("a"), Some(Bytes::from("42")));
+    /// assert_eq!(lfu.append("b", b"2"), false);
+    /// ```
+    pub fn append(&mut self, key: &str, extra: &[u8]) -> bool {
+        let key = self.normalize(key).into_owned();
+        let previous = match self.items.get(&key) {
+            Some(item) => self.decompress(item.data.clone()),
+            None => return false,
+        };
+        if self.check_value_size(previous.len() + extra.len()).is_err() {
+            return false;
+        }
+        let mut item = match self.remove_item(&key) {
+            Some(item) => item,
+            None => return false,
+        };
+        let mut value = BytesMut::with_capacity(previous.len() + extra.len());
+        value.extend_from_slice(&previous);
+        value.extend_from_slice(extra);
+        let value = value.freeze();
+        let inserted = self.on_insert.as_ref().map(|_| value.clone());
+        let value = match &self.compressor {
+            Some(compressor) => compressor.compress(&value),
+            None => value,
+        };
+        // keep whatever cost or key bytes the entry was charged on top of its length
+        item.size = (item.size + self.charge(value.len()))
+            .saturating_sub(self.charge(item.data.len()))
+            .max(1);
+        item.data = value;
+        self.signal_full(item.size);
+        self.evict_to_fit(item.size);
+        self.attach_item(key.clone(), item);
+        if let (Some(on_insert), Some(value)) = (self.on_insert.as_mut(), inserted) {
+            (on_insert.0)(&key, &value);
+        }
+        true
+    }
+    ///
+    /// Insert every entry in order, same as calling `insert` for each
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert_many(vec![("a".to_string(), Bytes::from("b")), ("c".to_string(), Bytes::from("d"))]);
+    /// assert_eq!(lfu.len(), 2);
+    /// ```
+    pub fn insert_many(&mut self, entries: impl IntoIterator<Item = (String, Bytes)>) {
+        for (key, value) in entries {
+            self.insert(key, value);
+        }
+    }
+    ///
+    /// Replace whole contents with given entries, e.g. when reloading a snapshot. Same as `clear`
+    /// followed by `insert_many`: configuration and history are kept, entries are inserted in order
+    /// and evicted as usual if they don't fit into max_size.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.replace_all(vec![("c".to_string(), Bytes::from("d"))]);
+    /// assert_eq!(lfu.key_snapshot(), vec!["c".to_string()]);
+    /// ```
+    pub fn replace_all(&mut self, entries: impl IntoIterator<Item = (String, Bytes)>) {
+        self.clear();
+        self.insert_many(entries);
+    }
+    ///
+    /// Insert a value and hand back the entry evicted to make room for it, but only if
+    /// exactly one entry was evicted. No eviction or several evictions return None.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(5);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// assert_eq!(lfu.swap_in("b".to_string(), Bytes::from("43")), None);
+    /// lfu.get("a");
+    /// assert_eq!(
+    ///     lfu.swap_in("c".to_string(), Bytes::from("44")),
+    ///     Some(("b".to_string(), Bytes::from("43")))
+    /// );
+    /// ```
+    pub fn swap_in(&mut self, key: String, value: Bytes) -> Option<(String, Bytes)> {
+        let (_, mut evicted) = self.insert_evicting(key, value);
+        if evicted.len() == 1 {
+            evicted
+                .pop()
+                .map(|(key, value)| (key, self.decompress(value)))
+        } else {
+            None
+        }
+    }
+    ///
+    /// Insert a value and iterate over entries evicted to make room, coldest first. Eviction itself
+    /// happens during the call so cache is consistent even if the iterator isn't consumed; evicted
+    /// values are only decompressed as they're yielded.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(5);
+    /// lfu.insert("a".to_string(), Bytes::from("42"));
+    /// lfu.insert("b".to_string(), Bytes::from("43"));
+    /// let evicted: Vec<(String, Bytes)> = lfu
+    ///     .insert_draining_evictions("c".to_string(), Bytes::from("44"))
+    ///     .collect();
+    /// assert_eq!(evicted, vec![("b".to_string(), Bytes::from("43"))]);
+    /// ```
+    pub fn insert_draining_evictions(
+        &mut self,
+        key: String,
+        value: Bytes,
+    ) -> impl Iterator<Item = (String, Bytes)> + '_ {
+        let (_, evicted) = self.insert_evicting(key, value);
+        let lfu = &*self;
+        evicted
+            .into_iter()
+            .map(move |(key, data)| (key, lfu.decompress(data)))
+    }
+    ///
+    /// Insert returning both previous value for the key and all entries evicted to make room
+    ///
+    fn insert_evicting(
+        &mut self,
+        key: String,
+        value: Bytes,
+    ) -> (Option<Bytes>, Vec<(String, Bytes)>) {
+        self.insert_charged(key, value, None)
+    }
+    ///
+    /// `insert_evicting` charging `cost` against max_size instead of (compressed) value length when given
+    ///
+    fn insert_charged(
+        &mut self,
+        key: String,
+        value: Bytes,
+        cost: Option<usize>,
+    ) -> (Option<Bytes>, Vec<(String, Bytes)>) {
+        if self.check_value_size(value.len()).is_err() {
+            return (None, vec![]);
+        }
+        let key = self.normalize_owned(key);
+        let inserted = self
+            .on_insert
+            .as_ref()
+            .map(|_| (key.clone(), value.clone()));
+        let value = match &self.compressor {
+            Some(compressor) => compressor.compress(&value),
+            None => value,
+        };
+        let size = self.charge_entry(&key, cost.unwrap_or(value.len()));
+        let previous = self.remove_item(&key);
+        self.release_reservation(&key);
+        let evicted = match &previous {
+            // overwriting with a value of the same size doesn't change current_size
+            Some(item) if item.size == size => vec![],
+            _ => {
+                self.signal_full(size);
+                self.evict_to_fit(size)
+            }
+        };
+
+        self.tombstones.remove(&key);
+        let mut item = Item::new(value, size);
+        item.parent = self.initial_frequency.min(self.top_level());
+        item.seq = self.next_seq;
+        self.next_seq += 1;
+        if let Some(previous) = &previous {
+            item.pinned = previous.pinned;
+            if self.preserve_frequency {
+                item.parent = previous.parent;
+            }
+        }
+        self.attach_item(key, item);
+        if let (Some(on_insert), Some((key, value))) = (self.on_insert.as_mut(), inserted) {
+            (on_insert.0)(&key, &value);
+        }
+        let previous = previous.map(|item| self.decompress(item.data));
+        (previous, evicted)
+    }
+    ///
+    /// Track whether `incoming` bytes run into max_size, firing on_full when they first do
+    ///
+    fn signal_full(&mut self, incoming: usize) {
+        if self.is_unbounded() {
+            return;
+        }
+        let full = self.current_size + incoming >= self.max_size;
+        if full && !self.full {
+            if let Some(on_full) = self.on_full.as_mut() {
+                (on_full.0)();
+            }
+        }
+        self.full = full;
+    }
+    ///
+    /// Restore a stored value to its original form when compression is configured
+    ///
+    fn decompress(&self, data: Bytes) -> Bytes {
+        match &self.compressor {
+            Some(compressor) => compressor.decompress(&data),
+            None => data,
+        }
+    }
+    ///
+    /// Put item into cache and its frequency node, counting its size. Inverse of `remove_item`.
+    ///
+    fn attach_item(&mut self, key: String, item: Item) {
+        self.current_size += item.size;
+        FrequencyNode::at(&mut self.frequency_list, item.parent).insert_key(
+            key.clone(),
+            item.seq,
+            &self.items,
+        );
+        self.items.insert(key, item);
+    }
+    ///
+    /// Take item out of cache together with its frequency node entry, releasing its size
+    ///
+    fn remove_item(&mut self, key: &str) -> Option<Item> {
+        let key = self.normalize(key);
+        let key = key.as_ref();
+        let item = self.items.remove(key)?;
+        if let Some(frequency_node) = self.frequency_list.get_mut(item.parent) {
+            // victims sit at the back of their node, search from there
+            if let Some(idx) = frequency_node.items.iter().rposition(|x| x == key) {
+                frequency_node.items.remove(idx);
+            }
+        }
+        self.current_size -= item.size;
+        Some(item)
+    }
+    ///
+    /// Drop a reservation made by `reserve_slot` releasing its size, returns whether there was one
+    ///
+    fn release_reservation(&mut self, key: &str) -> bool {
+        let key = self.normalize(key);
+        match self.reservations.remove(key.as_ref()) {
+            Some(size) => {
+                self.current_size -= size;
+                true
+            }
+            None => false,
+        }
+    }
+    ///
+    /// Key in the form it's stored under, lowercased for `case_insensitive` caches
+    ///
+    fn normalize<'k>(&self, key: &'k str) -> Cow<'k, str> {
+        if self.case_insensitive && key.chars().any(|c| !c.to_lowercase().eq([c])) {
+            Cow::Owned(key.to_lowercase())
+        } else {
+            Cow::Borrowed(key)
+        }
+    }
+    ///
+    /// Owned counterpart of `normalize`, reuses `key` when it's already in stored form
+    ///
+    fn normalize_owned(&self, key: String) -> String {
+        if self.case_insensitive && key.chars().any(|c| !c.to_lowercase().eq([c])) {
+            key.to_lowercase()
+        } else {
+            key
+        }
+    }
+    ///
+    /// Refuse values longer than `max_value_size`
+    ///
+    fn check_value_size(&self, value_len: usize) -> Result<(), InsertError> {
+        match self.max_value_size {
+            Some(limit) if value_len > limit => Err(InsertError::ValueTooLarge {
+                size: value_len,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+    ///
+    /// Part of max_size taken by a value of given length. Empty values are charged a single byte
+    /// so that cache can't hold unbounded number of them.
+    ///
+    fn charge(&self, value_len: usize) -> usize {
+        value_len.max(1)
+    }
+    ///
+    /// Part of max_size taken by an entry under stored `key` with a value of given (compressed) length,
+    /// including key bytes when `count_key_bytes` is set
+    ///
+    fn charge_entry(&self, key: &str, value_len: usize) -> usize {
+        let key_bytes = if self.count_key_bytes { key.len() } else { 0 };
+        self.charge(value_len) + key_bytes
+    }
+    ///
+    /// Evict entries picked by eviction policy until `incoming` bytes fit into max_size
+    ///
+    fn evict_to_fit(&mut self, incoming: usize) -> Vec<(String, Bytes)> {
+        let mut evicted = vec![];
+        if self.is_unbounded() && self.max_entries.is_none() {
+            return evicted;
+        }
+        if self.eviction_policy == EvictionPolicy::WeightedRandom {
+            let mut rng = self.rng;
+            let victims = self.weighted_random_victims(incoming, &mut rng);
+            self.rng = rng;
+            for key in victims {
+                if let Some(data) = self.evict(key.clone()) {
+                    evicted.push((key, data));
+                }
+            }
+            return evicted;
+        }
+        // same walk as select_victims, but popping victims off their nodes as it goes,
+        // a node is left only once it has nothing evictable
+        let mut fnode_index = 0_usize;
+        let max_evictions = self.max_evictions.unwrap_or(usize::MAX);
+        while (self.current_size + incoming >= self.max_size
+            || self.at_entry_limit(self.items.len()))
+            && evicted.len() < max_evictions
+            && fnode_index < self.frequency_list.len()
+        {
+            match self.pop_victim(fnode_index) {
+                Some((key, item)) => {
+                    let data = self.retire(key.clone(), item);
+                    evicted.push((key, data));
+                }
+                None => fnode_index += 1,
+            }
+        }
+        evicted
+    }
+    ///
+    /// Take the first victim of frequency node at given index out of cache, releasing its size.
+    /// Victims sit at the back of their node, so this is usually a plain pop.
+    ///
+    fn pop_victim(&mut self, fnode_index: usize) -> Option<(String, Item)> {
+        let frequency_node = self.frequency_list.get(fnode_index)?;
+        let idx = frequency_node.items.iter().rposition(|key| {
+            self.items
+                .get(key)
+                .is_some_and(|item| self.is_evictable(key, item))
+        })?;
+        let key = self.frequency_list[fnode_index].items.remove(idx);
+        let item = self.items.remove(&key)?;
+        self.current_size -= item.size;
+        Some((key, item))
+    }
+    ///
+    /// Drop a key from cache recording it in history and retaining its data if configured, returns evicted value
+    ///
+    fn evict(&mut self, key: String) -> Option<Bytes> {
+        let item = self.remove_item(&key)?;
+        Some(self.retire(key, item))
+    }
+    ///
+    /// Record an item already taken out of cache as evicted, returns its value
+    ///
+    fn retire(&mut self, key: String, item: Item) -> Bytes {
+        self.add_to_history(key.clone(), item.parent);
+        if self.retained_capacity > 0 {
+            self.retained.truncate(self.retained_capacity - 1);
+            self.retained.push_front((key, item.clone()));
+        }
+        item.data
+    }
+    ///
+    /// Evictable keys of a frequency node, most recently inserted first. Pinned keys and ones
+    /// vetoed by `eviction_filter` are left out.
+    ///
+    fn node_victims<'a>(
+        &'a self,
+        frequency_node: &'a FrequencyNode,
+    ) -> impl Iterator<Item = (&'a String, &'a Item)> + 'a {
+        // nodes are kept ordered by insertion sequence, so walking from the back yields the
+        // most recently inserted keys first
+        frequency_node
+            .items
+            .iter()
+            .rev()
+            .filter_map(move |key| self.items.get(key).map(|item| (key, item)))
+            .filter(move |(key, item)| self.is_evictable(key, item))
+    }
+    ///
+    /// Whether eviction may take an item, i.e. it's not pinned and `eviction_filter` doesn't veto it
+    ///
+    fn is_evictable(&self, key: &str, item: &Item) -> bool {
+        !item.pinned
+            && self
+                .eviction_filter
+                .as_ref()
+                .is_none_or(|filter| (filter.0)(key, &item.data, item.parent))
+    }
+    ///
+    /// Remember current frequency_list length for `peak_levels` before it shrinks
+    ///
+    fn note_levels(&mut self) {
+        self.max_levels_seen = self.peak_levels();
+    }
+    ///
+    /// Whether `count` entries leave no room for another one under `max_entries`
+    ///
+    fn at_entry_limit(&self, count: usize) -> bool {
+        self.max_entries.is_some_and(|limit| count >= limit)
+    }
+    ///
+    /// Whether eviction is disabled, see `unbounded`
+    ///
+    fn is_unbounded(&self) -> bool {
+        self.max_size == usize::MAX
+    }
+    ///
+    /// Highest frequency level allowed by `max_levels`
+    ///
+    fn top_level(&self) -> usize {
+        self.max_levels
+            .map_or(usize::MAX, |levels| levels.saturating_sub(1))
+    }
+}
+
+/// Snapshot of every tunable of a cache, see `LFU::config`. Fields are named after builders setting them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LfuConfig {
+    pub max_size: usize,
+    pub history_capacity: usize,
+    pub negative_cache: Option<Duration>,
+    pub max_frequency_cap: Option<usize>,
+    pub max_levels: Option<usize>,
+    /// whether values are compressed
+    pub compression: bool,
+    pub retain_evicted_data: usize,
+    pub case_insensitive: bool,
+    pub preserve_frequency_on_overwrite: bool,
+    pub initial_frequency: usize,
+    pub max_value_size: Option<usize>,
+    pub count_key_bytes: bool,
+    pub max_evictions_per_insert: Option<usize>,
+    pub max_entries: Option<usize>,
+    pub eviction_policy: EvictionPolicy,
+    /// whether an `on_insert` callback is set
+    pub on_insert: bool,
+    /// whether an `on_full` callback is set
+    pub on_full: bool,
+    /// whether an `eviction_filter` is set
+    pub eviction_filter: bool,
+}
+
+/// How victims are picked when a cache needs to make room, see `LFU::eviction_policy`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// strict LFU, coldest frequency nodes first
+    #[default]
+    Lfu,
+    /// random victims with probability inversely proportional to frequency + 1, biased towards cold
+    /// keys but avoiding correlated eviction storms. Deterministic for a given `eviction_seed`.
+    WeightedRandom,
+}
+
+/// Memory taken by a cache split by what it's used for, see `LFU::size_breakdown`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SizeBreakdown {
+    /// stored (possibly compressed) value bytes
+    pub value_bytes: usize,
+    /// key bytes, each key is held both by the item map and by its frequency node
+    pub key_bytes: usize,
+    /// frequency nodes and their key slots, excluding key bytes
+    pub frequency_list_bytes: usize,
+    /// history of evicted keys including their key bytes
+    pub history_bytes: usize,
+    /// misses remembered by negative caching including their key bytes
+    pub tombstone_bytes: usize,
+}
+
+/// Keys on which two caches disagree, see `LFU::diff`. Every list is sorted.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheDiff<'a> {
+    /// keys cached only by the cache `diff` was called on
+    pub only_in_self: Vec<&'a String>,
+    /// keys cached only by the other cache
+    pub only_in_other: Vec<&'a String>,
+    /// keys cached by both with different values or frequencies
+    pub changed: Vec<&'a String>,
+}
+
+impl CacheDiff<'_> {
+    /// Check if caches hold the same keys with the same values and frequencies
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Inconsistency between cache items, frequency nodes and size accounting found by `LFU::check_integrity`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityError {
+    /// key is listed in more than one place across frequency nodes
+    DuplicateKey { key: String },
+    /// frequency node lists a key that isn't cached
+    StaleKey { key: String, level: usize },
+    /// cached key isn't listed in any frequency node
+    MissingKey { key: String },
+    /// key sits in a different frequency node than its frequency says
+    WrongLevel {
+        key: String,
+        frequency: usize,
+        level: usize,
+    },
+    /// current_size doesn't match sizes of cached values and reservations
+    SizeMismatch { tracked: usize, actual: usize },
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityError::DuplicateKey { key } => {
+                write!(
+                    f,
+                    "key {:?} is listed more than once in frequency nodes",
+                    key
+                )
+            }
+            IntegrityError::StaleKey { key, level } => {
+                write!(
+                    f,
+                    "frequency node {} lists key {:?} which isn't cached",
+                    level, key
+                )
+            }
+            IntegrityError::MissingKey { key } => {
+                write!(f, "cached key {:?} isn't listed in any frequency node", key)
+            }
+            IntegrityError::WrongLevel {
+                key,
+                frequency,
+                level,
+            } => write!(
+                f,
+                "key {:?} has frequency {} but sits in frequency node {}",
+                key, frequency, level
+            ),
+            IntegrityError::SizeMismatch { tracked, actual } => write!(
+                f,
+                "current_size is {} but cached values take {}",
+                tracked, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+/// Reason an insert was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsertError {
+    /// value is longer than `max_value_size`
+    ValueTooLarge { size: usize, limit: usize },
+    /// key is already cached, see `LFU::insert_new`
+    KeyExists,
+}
+
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertError::ValueTooLarge { size, limit } => {
+                write!(
+                    f,
+                    "value of {} bytes exceeds max_value_size of {}",
+                    size, limit
+                )
+            }
+            InsertError::KeyExists => f.write_str("key is already cached"),
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+/// Load an existing map with every frequency at 0. max_size is raised above the default
+/// when needed so that all entries fit and nothing gets evicted on the way in.
+///
+/// ```
+/// use lfu_vecs::LFU;
+/// use bytes::Bytes;
+/// use std::collections::HashMap;
+/// let mut map = HashMap::new();
+/// map.insert("a".to_string(), Bytes::from("b"));
+/// let lfu = LFU::from(map);
+/// assert_eq!(lfu.contains_key("a"), true);
+/// ```
+impl From<HashMap<String, Bytes>> for LFU {
+    fn from(map: HashMap<String, Bytes>) -> Self {
+        let mut lfu = LFU::new();
+        let required: usize = map.values().map(|value| lfu.charge(value.len())).sum();
+        // eviction kicks in once current_size reaches max_size, so leave one byte spare
+        lfu.max_size = lfu.max_size.max(required + 1);
+        // insert in key order so that insertion sequence doesn't depend on hashing
+        let mut entries: Vec<(String, Bytes)> = map.into_iter().collect();
+        entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        for (key, value) in entries {
+            lfu.insert(key, value);
+        }
+        lfu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::*;
+    use bytes::Bytes;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn it_works() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        assert_eq!(lfu.get(&"a".to_string()), Some(Bytes::from("42")));
+    }
+    #[test]
+    fn test_max_size() {
+        let lfu = LFU::new().max_size(1000);
+        assert_eq!(lfu.max_size, 1000);
+    }
+
+    #[test]
+    fn test_evictions() {
+        let mut lfu = LFU::new().max_size(5);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("43"));
+        lfu.insert("d".to_string(), Bytes::from("43"));
+        assert_eq!(lfu.current_size(), 4);
+    }
+
+    #[test]
+    fn test_frequency() {
+        let mut lfu = LFU::new().max_size(3);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.get("a");
+        lfu.get("a");
+        assert_eq!(lfu.get_frequency("a"), 2);
+    }
+
+    #[test]
+    fn test_frequency_map() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        lfu.get("a");
+        lfu.get("a");
+        lfu.get("b");
+        let frequencies = lfu.frequency_map();
+        assert_eq!(frequencies.len(), 3);
+        for (key, frequency) in frequencies {
+            assert_eq!(lfu.get_frequency(&key), frequency);
+        }
+    }
+
+    #[test]
+    fn test_negative_cache() {
+        let mut lfu = LFU::new().negative_cache(Duration::from_secs(60));
+        let mut loads = 0;
+        for _ in 0..3 {
+            let value = lfu.get_or_load("missing", |_| {
+                loads += 1;
+                None
+            });
+            assert_eq!(value, None);
+        }
+        assert_eq!(loads, 1);
+        assert_eq!(lfu.current_size(), 0);
+        assert_eq!(lfu.get("missing"), None);
+    }
+
+    #[test]
+    fn test_negative_cache_expires() {
+        let mut lfu = LFU::new().negative_cache(Duration::from_millis(10));
+        let mut loads = 0;
+        lfu.get_or_load("a", |_| {
+            loads += 1;
+            None
+        });
+        std::thread::sleep(Duration::from_millis(20));
+        let value = lfu.get_or_load("a", |_| {
+            loads += 1;
+            Some(Bytes::from("42"))
+        });
+        assert_eq!(value, Some(Bytes::from("42")));
+        assert_eq!(loads, 2);
+    }
+
+    #[test]
+    fn test_negative_cache_bounded() {
+        let mut lfu = LFU::new()
+            .history_capacity(8)
+            .negative_cache(Duration::from_secs(60));
+        for i in 0..100 {
+            lfu.get_or_load(&format!("missing{}", i), |_| None);
+        }
+        assert_eq!(lfu.tombstones.len(), 8);
+        assert!(lfu.tombstones.contains_key("missing99"));
+        assert!(lfu.size_breakdown().tombstone_bytes > 0);
+
+        let mut loads = 0;
+        lfu.clear();
+        assert!(lfu.tombstones.is_empty());
+        lfu.get_or_load("missing99", |_| {
+            loads += 1;
+            None
+        });
+        assert_eq!(loads, 1);
+    }
+
+    #[test]
+    fn test_insert_without_head_node() {
+        let mut lfu = LFU::default().max_size(64);
+        assert!(lfu.frequency_list.is_empty());
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        assert_eq!(lfu.get("a"), Some(Bytes::from("42")));
+        assert_eq!(lfu.frequency_list[1].items, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_prealloc_levels() {
+        let mut lfu = LFU::new().prealloc_levels(4);
+        assert_eq!(lfu.frequency_list.len(), 4);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        for _ in 0..3 {
+            lfu.get("a");
+        }
+        assert_eq!(lfu.get_frequency("a"), 3);
+        assert_eq!(lfu.frequency_list.len(), 4);
+        lfu.get("a");
+        assert_eq!(lfu.frequency_list.len(), 5);
+    }
+
+    #[test]
+    fn test_find() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("v1:42"));
+        lfu.insert("b".to_string(), Bytes::from("v2:43"));
+        lfu.insert("c".to_string(), Bytes::from("v1:44"));
+        let mut keys: Vec<&String> = lfu.find(|value| value.starts_with(b"v1:")).collect();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "c"]);
+        assert_eq!(lfu.get_frequency("a"), 0);
+        assert_eq!(lfu.get_frequency("c"), 0);
+    }
+
+    #[test]
+    fn test_swap_in() {
+        let mut lfu = LFU::new().max_size(7);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        lfu.get("a");
+        lfu.get("b");
+        let victim = lfu.swap_in("d".to_string(), Bytes::from("45"));
+        assert_eq!(victim, Some(("c".to_string(), Bytes::from("44"))));
+        assert!(!lfu.contains_key("c"));
+        assert!(lfu.contains_key("d"));
+        assert_eq!(lfu.current_size(), 6);
+    }
+
+    #[test]
+    fn test_overwrite_does_not_double_count() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("a".to_string(), Bytes::from("4242"));
+        assert_eq!(lfu.current_size(), 4);
+        assert_eq!(lfu.frequency_list[0].items, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_same_size_overwrite_skips_eviction() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        let mut lfu = lfu.max_size(6);
+        lfu.insert("b".to_string(), Bytes::from("99"));
+        assert!(lfu.contains_key("a"));
+        assert!(lfu.contains_key("c"));
+        assert_eq!(lfu.get("b"), Some(Bytes::from("99")));
+        assert_eq!(lfu.current_size(), 6);
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("Hot".to_string(), Bytes::from("42"));
+        lfu.insert("hot".to_string(), Bytes::from("stale"));
+        for _ in 0..3 {
+            lfu.get("Hot");
+        }
+        assert!(lfu.rename("Hot", "hot".to_string()));
+        assert!(!lfu.contains_key("Hot"));
+        assert_eq!(lfu.get_frequency("hot"), 3);
+        assert_eq!(lfu.frequency_list[3].items, vec!["hot".to_string()]);
+        assert!(lfu.frequency_list[0].items.is_empty());
+        assert_eq!(lfu.current_size(), 2);
+        assert_eq!(lfu.get("hot"), Some(Bytes::from("42")));
+        assert!(!lfu.rename("missing", "other".to_string()));
+    }
+
+    #[test]
+    fn test_max_frequency_cap() {
+        let mut lfu = LFU::new().max_frequency_cap(5);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        for _ in 0..100 {
+            assert_eq!(lfu.get("a"), Some(Bytes::from("42")));
+        }
+        assert_eq!(lfu.get_frequency("a"), 5);
+        assert_eq!(lfu.frequency_list.len(), 6);
+        assert_eq!(lfu.frequency_list[5].items, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_insert_from_chunks() {
+        let mut lfu = LFU::new().max_size(1024);
+        let chunks = vec![Bytes::from("abc"), Bytes::from("de"), Bytes::from("f")];
+        lfu.insert_from_chunks("a".to_string(), chunks);
+        assert_eq!(lfu.current_size(), 6);
+        assert_eq!(lfu.get("a"), Some(Bytes::from("abcdef")));
+    }
+
+    #[test]
+    fn test_would_evict() {
+        let mut lfu = LFU::new().max_size(9);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        lfu.insert("d".to_string(), Bytes::from("45"));
+        lfu.get("a");
+        lfu.get("b");
+        lfu.get("b");
+        let predicted = lfu.would_evict(6);
+        assert_eq!(lfu.current_size(), 8);
+        assert_eq!(lfu.items.len(), 4);
+        let (_, evicted) = lfu.insert_evicting("e".to_string(), Bytes::from("424242"));
+        let evicted: Vec<String> = evicted.into_iter().map(|(key, _)| key).collect();
+        assert_eq!(predicted, evicted);
+        assert_eq!(evicted, vec!["d", "c", "a"]);
+        assert_eq!(lfu.get_frequency("b"), 2);
+    }
+
+    #[test]
+    fn test_eviction_drains_a_node() {
+        let mut lfu = LFU::new().max_size(5);
+        for key in ["a", "b", "c", "d"] {
+            lfu.insert(key.to_string(), Bytes::from("x"));
+        }
+        lfu.insert("e".to_string(), Bytes::from("xxxx"));
+        assert_eq!(lfu.key_snapshot(), vec!["e".to_string()]);
+        assert_eq!(lfu.current_size(), 4);
+    }
+
+    #[test]
+    fn test_would_evict_matches_eviction_past_pinned() {
+        let mut lfu = LFU::new()
+            .max_size(11)
+            .eviction_filter(|key, _, _| key != "c");
+        for key in ["a", "b", "c", "d", "e"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        lfu.pin("e");
+        lfu.get("d");
+        let predicted = lfu.would_evict(4);
+        let (_, evicted) = lfu.insert_evicting("f".to_string(), Bytes::from("4242"));
+        let evicted: Vec<String> = evicted.into_iter().map(|(key, _)| key).collect();
+        assert_eq!(predicted, evicted);
+        assert_eq!(evicted, vec!["b", "a"]);
+        assert_eq!(lfu.current_size(), 10);
+    }
+
+    #[test]
+    fn test_history_capacity() {
+        let lfu = LFU::new().max_size(1 << 30);
+        assert_eq!(lfu.history_capacity, DEFAULT_HISTORY_CAPACITY);
+
+        let mut lfu = LFU::new().max_size(1 << 20).history_capacity(8);
+        for i in 0..20 {
+            lfu.insert(i.to_string(), Bytes::from(vec![0; 600_000]));
+        }
+        assert_eq!(lfu.history.len(), 8);
+        assert!(lfu.has_evicted_recently("18"));
+        assert!(!lfu.has_evicted_recently("10"));
+    }
+
+    #[test]
+    fn test_set_frequency() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        assert!(lfu.set_frequency("a", 10));
+        assert_eq!(lfu.get_frequency("a"), 10);
+        assert_eq!(lfu.frequency_list[10].items, vec!["a".to_string()]);
+        assert_eq!(lfu.frequency_list[0].items, vec!["b".to_string()]);
+        assert!(lfu.set_frequency("a", 1));
+        assert_eq!(lfu.get_frequency("a"), 1);
+        assert!(lfu.frequency_list[10].items.is_empty());
+        assert_eq!(lfu.frequency_list[1].items, vec!["a".to_string()]);
+        assert!(!lfu.set_frequency("c", 1));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        lfu.get("a");
+        lfu.get("c");
+        lfu.get("c");
+        let snapshot = lfu.snapshot();
+        lfu.clear();
+        assert_eq!(lfu.current_size(), 0);
+        assert!(lfu.snapshot().is_empty());
+        for (key, value, frequency) in snapshot.clone() {
+            lfu.insert_with_frequency(key, value, frequency);
+        }
+        assert_eq!(lfu.snapshot(), snapshot);
+        assert_eq!(lfu.current_size(), 6);
+    }
+
+    #[test]
+    fn test_average_frequency() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        lfu.insert("d".to_string(), Bytes::from("45"));
+        lfu.set_frequency("a", 1);
+        lfu.set_frequency("b", 2);
+        lfu.set_frequency("c", 6);
+        assert_eq!(lfu.len(), 4);
+        assert_eq!(lfu.average_frequency(), 2.25);
+    }
+
+    #[test]
+    fn test_remove_many() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("4444"));
+        lfu.get("a");
+        assert_eq!(lfu.remove_many(&["a", "c", "x", "a"]), 2);
+        assert_eq!(lfu.len(), 1);
+        assert!(lfu.contains_key("b"));
+        assert_eq!(lfu.current_size(), 2);
+        assert!(lfu.frequency_list[1].items.is_empty());
+        assert_eq!(lfu.frequency_list[0].items, vec!["b".to_string()]);
+        assert!(!lfu.has_evicted_recently("a"));
+    }
+
+    #[test]
+    fn test_eviction_history() {
+        let mut lfu = LFU::new().max_size(3);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        std::thread::sleep(Duration::from_millis(5));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        let history: Vec<(&String, Instant)> = lfu.eviction_history().collect();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].0, "b");
+        assert_eq!(history[1].0, "a");
+        assert!(history[0].1 > history[1].1);
+        assert!(lfu.has_evicted_recently("a"));
+    }
+
+    #[test]
+    fn test_pin() {
+        let mut lfu = LFU::new().max_size(7);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        lfu.get("b");
+        lfu.get("c");
+        assert!(lfu.pin("a"));
+        for key in &["d", "e", "f"] {
+            lfu.insert(key.to_string(), Bytes::from("45"));
+        }
+        assert!(lfu.contains_key("a"));
+        assert!(lfu.has_evicted_recently("c"));
+        assert!(lfu.has_evicted_recently("d"));
+        assert!(lfu.has_evicted_recently("e"));
+        assert!(lfu.unpin("a"));
+        assert!(!lfu.items["a"].pinned);
+        assert!(!lfu.pin("x"));
+    }
+
+    #[test]
+    fn test_take() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("4343"));
+        lfu.get("b");
+        assert_eq!(lfu.take("b"), Some(Bytes::from("4343")));
+        assert!(!lfu.contains_key("b"));
+        assert_eq!(lfu.current_size(), 2);
+        assert!(lfu.frequency_list[1].items.is_empty());
+        assert_eq!(lfu.take("b"), None);
+    }
+
+    /// run length encoding, good enough to shrink repetitive test values
+    #[derive(Debug)]
+    struct RunLength;
+
+    impl Compressor for RunLength {
+        fn compress(&self, data: &Bytes) -> Bytes {
+            let mut compressed: Vec<u8> = vec![];
+            for &byte in data.iter() {
+                match compressed.len() {
+                    len if len >= 2 && compressed[len - 1] == byte && compressed[len - 2] < 255 => {
+                        compressed[len - 2] += 1
+                    }
+                    _ => compressed.extend_from_slice(&[1, byte]),
+                }
+            }
+            Bytes::from(compressed)
+        }
+
+        fn decompress(&self, data: &Bytes) -> Bytes {
+            data.chunks(2)
+                .flat_map(|run| std::iter::repeat_n(run[1], run[0] as usize))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_compression() {
+        let mut lfu = LFU::new().max_size(1024).compression(RunLength);
+        let value = Bytes::from(vec![b'a'; 100]);
+        lfu.insert("a".to_string(), value.clone());
+        assert_eq!(lfu.current_size(), 2);
+        assert_eq!(lfu.get("a"), Some(value.clone()));
+        assert_eq!(lfu.get_frequency("a"), 1);
+        let previous = lfu.insert("a".to_string(), Bytes::from("abc"));
+        assert_eq!(previous, Some(value));
+        assert_eq!(lfu.current_size(), 6);
+    }
+
+    /// every read that hands out values gives them back as inserted
+    fn assert_reads_decompressed(mut lfu: LFU) {
+        let value = Bytes::from(vec![b'a'; 100]);
+        let default = Bytes::new();
+        lfu.insert("a".to_string(), value.clone());
+        assert!(lfu.current_size() < value.len());
+        assert_eq!(lfu.peek("a"), Some(value.clone()));
+        assert_eq!(lfu.peek_many(&["a"]), vec![Some(value.clone())]);
+        assert_eq!(lfu.get("a"), Some(value.clone()));
+        assert_eq!(lfu.get_with("a", false), Some(value.clone()));
+        assert_eq!(lfu.get_with_frequency("a"), Some((value.clone(), 2)));
+        assert_eq!(lfu.get_or("a", &default), value);
+        assert_eq!(lfu.get_or_load("a", |_| None), Some(value.clone()));
+        assert_eq!(
+            lfu.get_or_insert_with_status("a".to_string(), Bytes::new),
+            Ok((value.clone(), false))
+        );
+        assert_eq!(lfu.to_btree_map()["a"], value);
+        assert_eq!(lfu.snapshot()[0].1, value);
+        assert_eq!(lfu.keys_with_value(&value), vec!["a"]);
+        assert_eq!(lfu.find(|v| *v == value).count(), 1);
+        assert_eq!(lfu.take("a"), Some(value));
+    }
+
+    #[test]
+    fn test_compressed_reads() {
+        assert_reads_decompressed(LFU::new().max_size(1024).compression(RunLength));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_reads() {
+        assert_reads_decompressed(LFU::new().max_size(1024).compression(Gzip));
+    }
+
+    #[test]
+    fn test_victims_to_free() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        lfu.insert("b".to_string(), Bytes::from("22"));
+        lfu.insert("c".to_string(), Bytes::from("333"));
+        lfu.insert("d".to_string(), Bytes::from("4444"));
+        lfu.get("c");
+        lfu.get("d");
+        lfu.get("d");
+        let victims = lfu.victims_to_free(4);
+        assert_eq!(
+            victims,
+            vec!["b".to_string(), "a".to_string(), "c".to_string()]
+        );
+        let freed: usize = victims.iter().map(|key| lfu.items[key].data.len()).sum();
+        assert!(freed >= 4);
+        assert!(freed - lfu.items[victims.last().unwrap()].data.len() < 4);
+        assert_eq!(lfu.len(), 4);
+        assert!(lfu.victims_to_free(0).is_empty());
+    }
+
+    #[test]
+    fn test_setters() {
+        for &large in &[true, false] {
+            let mut lfu = LFU::new();
+            if large {
+                lfu.set_max_size(1 << 20).set_history_capacity(16);
+            }
+            lfu.set_max_frequency_cap(3)
+                .set_negative_cache(Duration::from_secs(1));
+            assert_eq!(lfu.max_size, if large { 1 << 20 } else { 64 });
+            assert_eq!(
+                lfu.history_capacity,
+                if large { 16 } else { DEFAULT_HISTORY_CAPACITY }
+            );
+            assert_eq!(lfu.max_frequency, Some(3));
+            assert_eq!(lfu.negative_ttl, Some(Duration::from_secs(1)));
+        }
+    }
+
+    #[test]
+    fn test_empty_values_are_bounded() {
+        let mut lfu = LFU::new().max_size(16);
+        for i in 0..1000 {
+            lfu.insert(i.to_string(), Bytes::new());
+        }
+        assert!(lfu.len() < 16);
+        assert_eq!(lfu.current_size(), lfu.len());
+        assert_eq!(lfu.would_evict_any(0), lfu.would_evict_any(1));
+    }
+
+    #[test]
+    fn test_update_frequencies() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert_with_frequency("a".to_string(), Bytes::from("42"), 1);
+        lfu.insert_with_frequency("b".to_string(), Bytes::from("43"), 4);
+        lfu.insert_with_frequency("c".to_string(), Bytes::from("44"), 9);
+        lfu.update_frequencies(|_, frequency| frequency / 2);
+        assert_eq!(lfu.get_frequency("a"), 0);
+        assert_eq!(lfu.get_frequency("b"), 2);
+        assert_eq!(lfu.get_frequency("c"), 4);
+        assert_eq!(lfu.frequency_list.len(), 5);
+        assert_eq!(lfu.frequency_list[0].items, vec!["a".to_string()]);
+        assert_eq!(lfu.frequency_list[2].items, vec!["b".to_string()]);
+        assert_eq!(lfu.frequency_list[4].items, vec!["c".to_string()]);
+        assert!(lfu.frequency_list[1].items.is_empty());
+    }
+
+    #[test]
+    fn test_max_levels() {
+        let mut lfu = LFU::new().max_size(1024).max_levels(4);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        for _ in 0..50 {
+            lfu.get("a");
+            lfu.get("b");
+        }
+        assert!(lfu.frequency_list.len() <= 4);
+        assert_eq!(lfu.get_frequency("a"), 3);
+        lfu.set_frequency("a", 100);
+        lfu.update_frequencies(|_, frequency| frequency * 10);
+        assert!(lfu.frequency_list.len() <= 4);
+        assert_eq!(lfu.frequency_list[3].items.len(), 2);
+    }
+
+    #[test]
+    fn test_split_off() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("user:1".to_string(), Bytes::from("42"));
+        lfu.insert("user:2".to_string(), Bytes::from("4343"));
+        lfu.insert("post:1".to_string(), Bytes::from("444"));
+        lfu.get("user:2");
+        lfu.get("post:1");
+        let users = lfu.split_off(|key| key.starts_with("user:"));
+        assert_eq!(users.max_size, 1024);
+        assert_eq!(users.len(), 2);
+        assert_eq!(users.current_size(), 6);
+        assert_eq!(users.frequency_map().get("user:2"), Some(&1));
+        assert_eq!(users.frequency_list[1].items, vec!["user:2".to_string()]);
+        assert_eq!(lfu.len(), 1);
+        assert_eq!(lfu.current_size(), 3);
+        assert_eq!(lfu.get_frequency("post:1"), 1);
+        assert!(lfu.frequency_list[0].items.is_empty());
+    }
+
+    #[test]
+    fn test_setters_match_builders() {
+        let built = LFU::new()
+            .max_size(1024)
+            .retain_evicted_data(4)
+            .preserve_frequency_on_overwrite(true)
+            .initial_frequency(2)
+            .max_value_size(8)
+            .eviction_policy(EvictionPolicy::WeightedRandom)
+            .max_evictions_per_insert(3)
+            .count_key_bytes(true);
+        let mut set = LFU::new();
+        set.set_max_size(1024)
+            .set_retain_evicted_data(4)
+            .set_preserve_frequency_on_overwrite(true)
+            .set_initial_frequency(2)
+            .set_max_value_size(8)
+            .set_eviction_policy(EvictionPolicy::WeightedRandom)
+            .set_max_evictions_per_insert(3)
+            .set_count_key_bytes(true);
+        assert_eq!(set.config(), built.config());
+
+        let mut first = LFU::new().eviction_seed(7);
+        let mut second = LFU::new();
+        second.set_eviction_seed(7);
+        assert_eq!(first.rng.next_u64(), second.rng.next_u64());
+    }
+
+    #[test]
+    fn test_split_off_keeps_settings() {
+        let mut lfu = LFU::new()
+            .max_size(1024)
+            .case_insensitive(true)
+            .retain_evicted_data(4);
+        lfu.insert("User:1".to_string(), Bytes::from("42"));
+        let users = lfu.split_off(|key| key.starts_with("user:"));
+        assert!(users.case_insensitive);
+        assert_eq!(users.retained_capacity, 4);
+        assert!(users.contains_key("USER:1"));
+    }
+
+    #[test]
+    fn test_evicted_frequency() {
+        let mut lfu = LFU::new().max_size(5);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.set_frequency("a", 7);
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("4444"));
+        assert!(!lfu.contains_key("a"));
+        assert_eq!(lfu.evicted_frequency("a"), Some(7));
+        assert_eq!(lfu.evicted_frequency("b"), Some(0));
+        assert_eq!(lfu.evicted_frequency("c"), None);
+    }
+
+    #[test]
+    fn test_get_after_frequency_list_shrunk() {
+        let mut lfu = LFU::new().max_size(1024).prealloc_levels(16);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.set_frequency("a", 5);
+        lfu.compact();
+        assert_eq!(lfu.frequency_list.len(), 6);
+        // simulate nodes lost under a key, its level is past the end of frequency_list
+        lfu.frequency_list.truncate(2);
+        assert_eq!(lfu.get("a"), Some(Bytes::from("42")));
+        assert_eq!(lfu.get_frequency("a"), 6);
+        assert_eq!(lfu.frequency_list.len(), 7);
+        assert_eq!(lfu.frequency_list[6].items, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.get("b");
+        for (key, value) in lfu.iter_mut() {
+            let mut transformed = key.clone().into_bytes();
+            transformed.push(value[0]);
+            *value = Bytes::from(transformed);
+        }
+        assert_eq!(lfu.get_frequency("a"), 0);
+        assert_eq!(lfu.get_frequency("b"), 1);
+        assert_eq!(lfu.get("a"), Some(Bytes::from("a4")));
+        assert_eq!(lfu.get("b"), Some(Bytes::from("b4")));
+        assert_eq!(lfu.current_size(), 4);
+    }
+
+    #[test]
+    fn test_from_hash_map() {
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(i.to_string(), Bytes::from("42"));
+        }
+        let lfu = LFU::from(map);
+        assert_eq!(lfu.len(), 100);
+        assert_eq!(lfu.current_size(), 200);
+        assert!((0..100).all(|i| lfu.contains_key(&i.to_string())));
+        assert!(lfu
+            .frequency_map()
+            .values()
+            .all(|&frequency| frequency == 0));
+        assert_eq!(LFU::from(HashMap::new()).max_size, 64);
+    }
+
+    #[test]
+    fn test_largest_value() {
+        let mut lfu = LFU::new().max_size(1024);
+        assert_eq!(lfu.largest_key(), None);
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        lfu.insert("b".to_string(), Bytes::from("12345"));
+        lfu.insert("c".to_string(), Bytes::from("123"));
+        lfu.insert("d".to_string(), Bytes::from("12345"));
+        assert_eq!(lfu.largest_value_size(), 5);
+        assert_eq!(lfu.largest_key(), Some(&"b".to_string()));
+    }
+
+    #[test]
+    fn test_on_insert() {
+        use std::sync::{Arc, Mutex};
+
+        let written = Arc::new(Mutex::new(vec![]));
+        let store = Arc::clone(&written);
+        let mut lfu = LFU::new().max_size(1024).on_insert(move |key, value| {
+            store.lock().unwrap().push((key.to_string(), value.clone()))
+        });
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("a".to_string(), Bytes::from("43"));
+        lfu.insert_many(vec![
+            ("b".to_string(), Bytes::from("44")),
+            ("c".to_string(), Bytes::from("45")),
+        ]);
+        assert_eq!(
+            *written.lock().unwrap(),
+            vec![
+                ("a".to_string(), Bytes::from("42")),
+                ("a".to_string(), Bytes::from("43")),
+                ("b".to_string(), Bytes::from("44")),
+                ("c".to_string(), Bytes::from("45")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shrink_history_capacity() {
+        let mut lfu = LFU::new().max_size(3);
+        for i in 0..10 {
+            lfu.insert(i.to_string(), Bytes::from("42"));
+        }
+        assert_eq!(lfu.history.len(), 9);
+        lfu.set_history_capacity(3);
+        let remembered: Vec<&String> = lfu.eviction_history().map(|(key, _)| key).collect();
+        assert_eq!(remembered, vec!["8", "7", "6"]);
+        assert!(!lfu.has_evicted_recently("5"));
+        lfu.insert("10".to_string(), Bytes::from("42"));
+        assert_eq!(lfu.history.len(), 3);
+        assert!(lfu.has_evicted_recently("9"));
+    }
+
+    #[test]
+    fn test_iter_by_size_desc() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("12"));
+        lfu.insert("b".to_string(), Bytes::from("12345"));
+        lfu.insert("c".to_string(), Bytes::from("1"));
+        lfu.insert("d".to_string(), Bytes::from("123"));
+        let sizes: Vec<(String, usize)> = lfu
+            .iter_by_size_desc()
+            .into_iter()
+            .map(|(key, size)| (key.clone(), size))
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![
+                ("b".to_string(), 5),
+                ("d".to_string(), 3),
+                ("a".to_string(), 2),
+                ("c".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_frequency_saturates() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        // a saturated key, frequency_list can't hold a node that far so only item is touched
+        lfu.items.get_mut("a").unwrap().parent = usize::MAX;
+        assert_eq!(
+            lfu.get_with_frequency("a"),
+            Some((Bytes::from("42"), usize::MAX))
+        );
+        assert_eq!(lfu.get("a"), Some(Bytes::from("42")));
+        assert_eq!(lfu.get_frequency("a"), usize::MAX);
+    }
+
+    #[test]
+    fn test_insert_if_newer() {
+        let mut lfu = LFU::new().max_size(1024);
+        assert!(lfu.insert_if_newer("a".to_string(), Bytes::from("v1"), 1));
+        lfu.get("a");
+        lfu.get("a");
+        assert!(lfu.insert_if_newer("a".to_string(), Bytes::from("v3"), 3));
+        assert!(!lfu.insert_if_newer("a".to_string(), Bytes::from("v2"), 2));
+        assert!(!lfu.insert_if_newer("a".to_string(), Bytes::from("v3"), 3));
+        assert_eq!(lfu.get_frequency("a"), 2);
+        assert_eq!(lfu.items["a"].data, Bytes::from("v3"));
+        assert!(lfu.insert_if_newer("a".to_string(), Bytes::from("v4"), 4));
+        assert_eq!(lfu.get("a"), Some(Bytes::from("v4")));
+    }
+
+    #[test]
+    fn test_insert_if_newer_refused_value() {
+        let mut lfu = LFU::new().max_size(1024).max_value_size(2);
+        assert!(lfu.insert_if_newer("a".to_string(), Bytes::from("v1"), 1));
+        assert!(!lfu.insert_if_newer("a".to_string(), Bytes::from("v2 too long"), 2));
+        assert_eq!(lfu.items["a"].version, 1);
+        assert!(lfu.insert_if_newer("a".to_string(), Bytes::from("v2"), 2));
+        assert_eq!(lfu.get("a"), Some(Bytes::from("v2")));
+    }
+
+    #[test]
+    fn test_insert_if_newer_initial_frequency() {
+        let mut lfu = LFU::new().max_size(1024).initial_frequency(2);
+        assert!(lfu.insert_if_newer("a".to_string(), Bytes::from("v1"), 1));
+        assert_eq!(lfu.get_frequency("a"), 2);
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_readmit_retained() {
+        let mut lfu = LFU::new().max_size(5).retain_evicted_data(2);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.get("a");
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        lfu.insert("d".to_string(), Bytes::from("45"));
+        lfu.insert("e".to_string(), Bytes::from("46"));
+        assert_eq!(lfu.retained.len(), 2);
+        assert!(!lfu.contains_key("c"));
+        assert!(!lfu.contains_key("d"));
+        assert_eq!(lfu.set_max_size_and_readmit(4), 0);
+        assert_eq!(lfu.set_max_size_and_readmit(8), 1);
+        assert_eq!(lfu.current_size(), 6);
+        assert_eq!(lfu.retained.len(), 1);
+        assert_eq!(lfu.set_max_size_and_readmit(16), 1);
+        assert!(lfu.contains_key("c"));
+        assert!(lfu.contains_key("d"));
+        assert_eq!(lfu.get("d"), Some(Bytes::from("45")));
+        assert!(lfu.retained.is_empty());
+    }
+
+    #[test]
+    fn test_peek_many() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.get("b");
+        let frequencies = lfu.frequency_map();
+        let values = lfu.peek_many(&["b", "x", "a", "b"]);
+        assert_eq!(
+            values,
+            vec![
+                Some(Bytes::from("43")),
+                None,
+                Some(Bytes::from("42")),
+                Some(Bytes::from("43")),
+            ]
+        );
+        assert_eq!(lfu.frequency_map(), frequencies);
+    }
+
+    #[test]
+    fn test_working_set_bytes() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert_with_frequency("a".to_string(), Bytes::from("1"), 0);
+        lfu.insert_with_frequency("b".to_string(), Bytes::from("22"), 1);
+        lfu.insert_with_frequency("c".to_string(), Bytes::from("333"), 3);
+        lfu.insert_with_frequency("d".to_string(), Bytes::from("4444"), 5);
+        assert_eq!(lfu.working_set_bytes(0), 10);
+        assert_eq!(lfu.working_set_bytes(1), 9);
+        assert_eq!(lfu.working_set_bytes(3), 7);
+        assert_eq!(lfu.working_set_bytes(6), 0);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut lfu = LFU::new().max_size(1024).case_insensitive(true);
+        lfu.insert("Foo".to_string(), Bytes::from("42"));
+        assert!(lfu.contains_key("foo"));
+        assert!(lfu.contains_key("FOO"));
+        assert_eq!(lfu.get("foo"), Some(Bytes::from("42")));
+        assert_eq!(lfu.get_frequency("fOO"), 1);
+        assert_eq!(lfu.frequency_list[1].items, vec!["foo".to_string()]);
+        assert!(lfu.remove("FoO"));
+        assert!(lfu.is_empty());
+
+        let mut lfu = LFU::new().max_size(1024).case_insensitive(false);
+        lfu.insert("Foo".to_string(), Bytes::from("42"));
+        assert!(!lfu.contains_key("foo"));
+        assert_eq!(lfu.get("foo"), None);
+        assert_eq!(lfu.get("Foo"), Some(Bytes::from("42")));
+
+        // titlecase letters aren't uppercase but still have a lowercase form
+        let mut lfu = LFU::new().max_size(1024).case_insensitive(true);
+        lfu.insert("\u{1c5}".to_string(), Bytes::from("42"));
+        assert!(lfu.contains_key("\u{1c4}"));
+        assert!(lfu.contains_key("\u{1c6}"));
+    }
+
+    #[test]
+    fn test_case_insensitive_keyed_methods() {
+        let mut lfu = LFU::new().max_size(5).case_insensitive(true);
+        assert_eq!(
+            lfu.get_or_load("Foo", |_| Some(Bytes::from("1"))),
+            Some(Bytes::from("1"))
+        );
+        assert!(lfu.contains_key("foo"));
+
+        assert!(lfu.insert_if_newer("Bar".to_string(), Bytes::from("2"), 2));
+        assert!(!lfu.insert_if_newer("BAR".to_string(), Bytes::from("3"), 1));
+        assert_eq!(lfu.peek("bar"), Some(Bytes::from("2")));
+
+        assert!(lfu.pin("FOO"));
+        assert!(lfu.items["foo"].pinned);
+        assert!(lfu.unpin("fOo"));
+        assert!(!lfu.items["foo"].pinned);
+
+        assert!(lfu.rename("foo", "Baz".to_string()));
+        assert!(lfu.contains_key("baz"));
+        assert_eq!(lfu.get("BAZ"), Some(Bytes::from("1")));
+        assert!(lfu.remove("Baz"));
+        assert_eq!(lfu.key_snapshot(), vec!["bar".to_string()]);
+
+        lfu.get("bar");
+        for key in ["x", "y", "z", "w"] {
+            lfu.insert(key.to_string(), Bytes::from("4"));
+        }
+        assert!(lfu.has_evicted_recently("Z"));
+        assert_eq!(lfu.evicted_frequency("Z"), Some(0));
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_thrash_count() {
+        let mut lfu = LFU::new().max_size(3);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        assert_eq!(lfu.get("a"), Some(Bytes::from("42")));
+        assert_eq!(lfu.get("never"), None);
+        assert_eq!(lfu.thrash_count(), 0);
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        assert!(lfu.has_evicted_recently("a"));
+        assert_eq!(lfu.get("a"), None);
+        assert_eq!(lfu.thrash_count(), 1);
+        lfu.get_or_load("a", |_| Some(Bytes::from("42")));
+        assert_eq!(lfu.thrash_count(), 2);
+    }
+
+    #[test]
+    fn test_key_snapshot() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        lfu.remove("b");
+        let mut keys = lfu.key_snapshot();
+        keys.sort();
+        assert_eq!(keys, vec!["a".to_string(), "c".to_string()]);
+        for key in &keys {
+            lfu.remove(key);
+        }
+        assert!(lfu.is_empty());
+    }
+
+    #[test]
+    fn test_check_integrity_healthy() {
+        let mut lfu = LFU::new().max_size(7);
+        for key in &["a", "b", "c", "d", "e"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+            lfu.get("a");
+        }
+        lfu.insert("a".to_string(), Bytes::from("4"));
+        lfu.set_frequency("a", 3);
+        lfu.remove("e");
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_check_integrity_detects_corruption() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.get("a");
+
+        lfu.frequency_list[0].items.push("a".to_string());
+        assert!(matches!(
+            lfu.check_integrity(),
+            Err(IntegrityError::WrongLevel { .. }) | Err(IntegrityError::DuplicateKey { .. })
+        ));
+        lfu.frequency_list[0].items.pop();
+
+        lfu.frequency_list[1].items.push("a".to_string());
+        assert_eq!(
+            lfu.check_integrity(),
+            Err(IntegrityError::DuplicateKey {
+                key: "a".to_string()
+            })
+        );
+        lfu.frequency_list[1].items.pop();
+
+        lfu.frequency_list[0].items.push("x".to_string());
+        assert_eq!(
+            lfu.check_integrity(),
+            Err(IntegrityError::StaleKey {
+                key: "x".to_string(),
+                level: 0
+            })
+        );
+        lfu.frequency_list[0].items.pop();
+
+        lfu.frequency_list[0].items.clear();
+        assert_eq!(
+            lfu.check_integrity(),
+            Err(IntegrityError::MissingKey {
+                key: "b".to_string()
+            })
+        );
+        lfu.frequency_list[0].items.push("b".to_string());
+
+        lfu.current_size += 1;
+        assert_eq!(
+            lfu.check_integrity(),
+            Err(IntegrityError::SizeMismatch {
+                tracked: 5,
+                actual: 4
+            })
+        );
+        lfu.current_size -= 1;
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_nodes_ordered_by_insertion() {
+        let mut lfu = LFU::new();
+        for key in ["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from("x"));
+        }
+        // promoted out of insertion order
+        lfu.get("c");
+        lfu.get("a");
+        lfu.get("b");
+        assert_eq!(lfu.frequency_list[1].items, vec!["a", "b", "c"]);
+        assert_eq!(lfu.peek_lfu(), Some(&"c".to_string()));
+        lfu.rename("a", "d".to_string());
+        assert_eq!(lfu.frequency_list[1].items, vec!["d", "b", "c"]);
+        assert_eq!(lfu.eviction_order(), vec!["c", "b", "d"]);
+    }
+    #[test]
+    fn test_deterministic_eviction() {
+        fn run() -> Vec<String> {
+            let mut lfu = LFU::new().max_size(40).history_capacity(1000);
+            for i in 0..200 {
+                let key = format!("k{}", i % 37);
+                lfu.insert(key.clone(), Bytes::from(format!("{:03}", i)));
+                if i % 3 == 0 {
+                    lfu.get(&format!("k{}", i % 7));
+                }
+                if i % 50 == 0 {
+                    // re-files keys in HashMap iteration order
+                    lfu.update_frequencies(|_, frequency| frequency / 2);
+                }
+            }
+            lfu.eviction_history().map(|(key, _)| key.clone()).collect()
+        }
+        let first = run();
+        assert!(!first.is_empty());
+        for _ in 0..5 {
+            assert_eq!(run(), first);
+        }
+    }
+
+    #[test]
+    fn test_demote() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        for _ in 0..4 {
+            lfu.get("a");
+        }
+        assert!(lfu.demote("a"));
+        assert_eq!(lfu.get_frequency("a"), 3);
+        assert_eq!(lfu.frequency_list[3].items, vec!["a".to_string()]);
+        assert!(lfu.frequency_list[4].items.is_empty());
+
+        lfu.set_frequency("a", 0);
+        assert!(lfu.demote("a"));
+        assert_eq!(lfu.get_frequency("a"), 0);
+        assert_eq!(lfu.frequency_list[0].items, vec!["a".to_string()]);
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_get_with() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        for _ in 0..3 {
+            assert_eq!(lfu.get_with("a", false), Some(Bytes::from("42")));
+        }
+        assert_eq!(lfu.get_frequency("a"), 0);
+        for _ in 0..3 {
+            assert_eq!(lfu.get_with("a", true), Some(Bytes::from("42")));
+        }
+        assert_eq!(lfu.get_frequency("a"), 3);
+        assert_eq!(lfu.get_with("b", true), None);
+        assert_eq!(lfu.get_with("b", false), None);
+    }
+
+    #[test]
+    fn test_evict_below() {
+        let mut lfu = LFU::new().max_size(1024);
+        for (key, frequency) in &[("a", 0), ("b", 1), ("c", 2), ("d", 3), ("e", 5)] {
+            lfu.insert_with_frequency(key.to_string(), Bytes::from("42"), *frequency);
+        }
+        lfu.pin("b");
+        assert_eq!(lfu.evict_below(3), 2);
+        let mut keys = lfu.key_snapshot();
+        keys.sort();
+        assert_eq!(keys, vec!["b", "d", "e"]);
+        assert_eq!(lfu.current_size(), 6);
+        assert!(lfu.has_evicted_recently("a"));
+        assert!(lfu.has_evicted_recently("c"));
+        assert!(!lfu.has_evicted_recently("b"));
+        assert_eq!(lfu.evict_below(0), 0);
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_insert_tracked() {
+        for &preserve in &[false, true] {
+            let mut lfu = LFU::new()
+                .max_size(1024)
+                .preserve_frequency_on_overwrite(preserve);
+            assert_eq!(
+                lfu.insert_tracked("a".to_string(), Bytes::from("42")),
+                (None, 0)
+            );
+            lfu.get("a");
+            lfu.get("a");
+            let expected = if preserve { 2 } else { 0 };
+            assert_eq!(
+                lfu.insert_tracked("a".to_string(), Bytes::from("43")),
+                (Some(Bytes::from("42")), expected)
+            );
+            assert_eq!(lfu.get_frequency("a"), expected);
+            assert_eq!(lfu.check_integrity(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_level_keys() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        assert_eq!(lfu.level_keys(0), ["a".to_string(), "b".to_string()]);
+        assert!(lfu.level_keys(1).is_empty());
+        lfu.get("a");
+        assert_eq!(lfu.level_keys(0), ["b".to_string()]);
+        assert_eq!(lfu.level_keys(1), ["a".to_string()]);
+    }
+
+    #[test]
+    fn test_initial_frequency() {
+        for &(initial, survivor, victim) in &[(0, "b", "c"), (1, "c", "b")] {
+            let mut lfu = LFU::new().max_size(7).initial_frequency(initial);
+            lfu.insert_with_frequency("a".to_string(), Bytes::from("42"), 0);
+            lfu.insert_with_frequency("b".to_string(), Bytes::from("43"), 0);
+            lfu.insert("c".to_string(), Bytes::from("44"));
+            lfu.insert("d".to_string(), Bytes::from("45"));
+            assert!(lfu.contains_key(survivor));
+            assert!(!lfu.contains_key(victim));
+            assert_eq!(lfu.get_frequency("d"), initial);
+        }
+    }
+
+    #[test]
+    fn test_to_btree_map() {
+        let mut lfu = LFU::new().max_size(1024);
+        for key in &["d", "b", "e", "a", "c"] {
+            lfu.insert(key.to_string(), Bytes::from(format!("value {}", key)));
+        }
+        lfu.get("e");
+        let map = lfu.to_btree_map();
+        let keys: Vec<&str> = map.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["a", "b", "c", "d", "e"]);
+        for (key, value) in &map {
+            assert_eq!(lfu.peek(key), Some(value.clone()));
+        }
+        assert_eq!(map.len(), lfu.len());
+    }
+
+    #[test]
+    fn test_on_full() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let fired = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&fired);
+        let mut lfu = LFU::new().max_size(7).on_full(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        assert_eq!(fired.load(Ordering::SeqCst), 0);
+        for key in &["c", "d", "e", "f"] {
+            lfu.insert(key.to_string(), Bytes::from("44"));
+        }
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+
+        lfu.clear();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+        lfu.insert("d".to_string(), Bytes::from("45"));
+        assert_eq!(fired.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_insert_with_cost() {
+        let mut lfu = LFU::new().max_size(10);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.get("a");
+        lfu.get("b");
+        lfu.insert_with_cost("c".to_string(), Bytes::from("4"), 5);
+        assert_eq!(lfu.current_size(), 9);
+        assert_eq!(lfu.len(), 3);
+
+        // costly entry takes more budget than its single byte
+        lfu.insert("d".to_string(), Bytes::from("44"));
+        assert!(!lfu.contains_key("c"));
+        assert_eq!(lfu.current_size(), 6);
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_iter_with_level() {
+        let mut lfu = LFU::new().max_size(20);
+        for i in 0..30 {
+            lfu.insert(format!("k{}", i % 11), Bytes::from("42"));
+            lfu.get(&format!("k{}", i % 4));
+        }
+        lfu.demote("k1");
+        let placement: Vec<(&String, usize)> = lfu.iter_with_level().collect();
+        assert_eq!(placement.len(), lfu.len());
+        let frequencies = lfu.frequency_map();
+        for (key, level) in placement {
+            assert_eq!(frequencies[key], level);
+        }
+    }
+
+    #[test]
+    fn test_max_value_size() {
+        let mut lfu = LFU::new().max_size(8).max_value_size(3);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.get("a");
+        let before = lfu.snapshot();
+
+        assert_eq!(lfu.insert("c".to_string(), Bytes::from("424242")), None);
+        assert_eq!(
+            lfu.try_insert("a".to_string(), Bytes::from("4242")),
+            Err(InsertError::ValueTooLarge { size: 4, limit: 3 })
+        );
+        assert_eq!(lfu.snapshot(), before);
+        assert_eq!(lfu.current_size(), 4);
+        assert!(!lfu.has_evicted_recently("b"));
+
+        assert_eq!(
+            lfu.try_insert("a".to_string(), Bytes::from("424")),
+            Ok(Some(Bytes::from("42")))
+        );
+    }
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let mut lfu = LFU::new().max_size(1024).max_entries(8);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::new());
+        lfu.insert("ü".to_string(), Bytes::from(vec![0u8, 255, 7]));
+        lfu.get("a");
+        lfu.get("a");
+        lfu.set_frequency("ü", 7);
+        lfu.pin("b");
+
+        let mut buf = lfu.encode().unwrap();
+        let trailing = Bytes::from("next frame");
+        let mut stream = BytesMut::from(&buf[..]);
+        stream.extend_from_slice(&trailing);
+        let mut stream = stream.freeze();
+
+        let copy = LFU::decode(&mut stream).unwrap();
+        assert_eq!(stream, trailing);
+        assert_eq!(copy.snapshot(), lfu.snapshot());
+        assert_eq!(copy.max_size, 1024);
+        assert_eq!(copy.max_entries, Some(8));
+        assert_eq!(copy.current_size(), lfu.current_size());
+        assert!(copy.items["b"].pinned);
+        assert_eq!(copy.check_integrity(), Ok(()));
+
+        let mut truncated = buf.split_to(buf.len() - 1);
+        assert_eq!(
+            LFU::decode(&mut truncated).unwrap_err(),
+            DecodeError::UnexpectedEof
+        );
+        assert_eq!(truncated.len(), lfu.encode().unwrap().len() - 1);
+    }
+
+    #[test]
+    fn test_decode_rejects_huge_frequency() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        let frame = lfu.encode().unwrap();
+        // frequency follows magic, version, max_size, max_entries, count, key length and the key itself
+        let offset = 4 + 1 + 8 + 8 + 8 + 4 + 1;
+        for frequency in [u64::MAX, (1 << 20) + 1] {
+            let mut corrupt = BytesMut::from(&frame[..]);
+            corrupt[offset..offset + 8].copy_from_slice(&frequency.to_be_bytes());
+            let mut corrupt = corrupt.freeze();
+            let err = LFU::decode(&mut corrupt).unwrap_err();
+            assert!(matches!(
+                err,
+                DecodeError::FrequencyTooHigh(_) | DecodeError::Overflow
+            ));
+            assert_eq!(corrupt.len(), frame.len());
+        }
+        let mut buf = frame.clone();
+        assert_eq!(LFU::decode(&mut buf).unwrap().get_frequency("a"), 0);
+    }
+
+    #[test]
+    fn test_decode_enforces_max_entries() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        let mut buf = lfu.encode().unwrap();
+        assert_eq!(LFU::decode(&mut buf).unwrap().max_entries, None);
+
+        lfu.set_max_entries(1);
+        let mut buf = lfu.encode().unwrap();
+        assert_eq!(
+            LFU::decode(&mut buf).unwrap_err(),
+            DecodeError::TooManyEntries { count: 2, limit: 1 }
+        );
+    }
+
+    #[test]
+    fn test_sample_frequencies() {
+        let mut lfu = LFU::new().max_size(1024);
+        assert!(lfu.sample_frequencies(5).is_empty());
+        for i in 0..100 {
+            lfu.insert_with_frequency(format!("k{}", i), Bytes::from("4"), i % 10);
+        }
+        for &n in &[0, 1, 7, 33, 50, 99, 100, 250] {
+            for _ in 0..3 {
+                let sample = lfu.sample_frequencies(n);
+                assert_eq!(sample.len(), n.min(lfu.len()));
+                assert!(sample.iter().all(|&frequency| frequency < 10));
+            }
+        }
+
+        // same contents and same number of calls give the same samples
+        let build = || {
+            let mut lfu = LFU::new().max_size(1024);
+            for i in 0..100 {
+                lfu.insert_with_frequency(format!("k{}", i), Bytes::from("4"), i % 10);
+            }
+            lfu
+        };
+        let (first, second) = (build(), build());
+        for _ in 0..3 {
+            assert_eq!(first.sample_frequencies(7), second.sample_frequencies(7));
+        }
+        assert_eq!(first.sample_frequencies(10), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_promote_to_top() {
+        let mut lfu = LFU::new().max_size(1024);
+        for key in &["a", "b", "c", "d"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+            for _ in 0..3 {
+                lfu.get(key);
+            }
+        }
+        lfu.insert("cold".to_string(), Bytes::from("4"));
+        assert!(lfu.promote_to_top("cold"));
+        assert_eq!(lfu.get_frequency("cold"), 4);
+        assert!(!lfu.promote_to_top("missing"));
+
+        let mut victims = lfu.victims_to_free(lfu.current_size() - 1);
+        victims.sort();
+        assert_eq!(victims, vec!["a", "b", "c", "d"]);
+        assert_eq!(lfu.evict_below(4), 4);
+        assert_eq!(lfu.key_snapshot(), vec!["cold".to_string()]);
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let mut lfu = LFU::new().max_size(1024).history_capacity(16);
+        for (i, key) in ["a", "b", "c", "d", "e", "f"].iter().enumerate() {
+            lfu.insert_with_frequency(key.to_string(), Bytes::from("42"), i);
+        }
+        lfu.set_frequency("a", 9);
+        lfu.pin("b");
+        let lfu = lfu.rebuild(9);
+        let mut keys = lfu.key_snapshot();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "b", "e", "f"]);
+        assert_eq!(lfu.frequency_map()["f"], 5);
+        assert_eq!(lfu.frequency_map()["a"], 9);
+        assert_eq!(lfu.current_size(), 8);
+        assert!(lfu.has_evicted_recently("c"));
+        assert!(lfu.has_evicted_recently("d"));
+        assert_eq!(lfu.history_capacity, 16);
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_insert_draining_evictions() {
+        let mut lfu = LFU::new().max_size(8);
+        for key in &["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from(format!("{}{}", key, key)));
+        }
+        lfu.get("a");
+        lfu.get("a");
+        lfu.get("b");
+
+        let mut evicted = lfu.insert_draining_evictions("d".to_string(), Bytes::from("dddd"));
+        assert_eq!(evicted.next(), Some(("c".to_string(), Bytes::from("cc"))));
+        assert_eq!(evicted.next(), Some(("b".to_string(), Bytes::from("bb"))));
+        drop(evicted);
+        assert_eq!(lfu.len(), 2);
+        assert_eq!(lfu.current_size(), 6);
+
+        // evictions happen even when nothing is consumed
+        drop(lfu.insert_draining_evictions("e".to_string(), Bytes::from("ee")));
+        assert!(lfu.has_evicted_recently("d"));
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_last_access() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        let inserted = lfu.last_access("a").unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        lfu.peek("a");
+        assert_eq!(lfu.last_access("a"), Some(inserted));
+        lfu.get("a");
+        let read = lfu.last_access("a").unwrap();
+        assert!(read >= inserted + Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_eviction_order() {
+        let mut lfu = LFU::new().max_size(1024);
+        for i in 0..12 {
+            lfu.insert(format!("k{}", i), Bytes::from("42"));
+            for _ in 0..(i * 7 % 5) {
+                lfu.get(&format!("k{}", i));
+            }
+        }
+        lfu.pin("k3");
+        let order = lfu.eviction_order();
+        assert_eq!(order.len(), 11);
+        let mut popped = vec![];
+        while let Some((key, _)) = lfu.pop_lfu() {
+            popped.push(key);
+        }
+        assert_eq!(popped, order);
+        assert_eq!(lfu.key_snapshot(), vec!["k3".to_string()]);
+    }
+
+    #[test]
+    fn test_eviction_order_matches_insert() {
+        let mut lfu = LFU::new().max_size(25);
+        for i in 0..12 {
+            lfu.insert(format!("k{}", i), Bytes::from("42"));
+            for _ in 0..(i * 7 % 5) {
+                lfu.get(&format!("k{}", i));
+            }
+        }
+        lfu.pin("k3");
+        let order = lfu.eviction_order();
+        // 24 bytes cached, making room for 16 more takes 8 victims across several nodes
+        let (_, evicted) = lfu.insert_evicting("big".to_string(), Bytes::from(vec![0; 16]));
+        let evicted: Vec<String> = evicted.into_iter().map(|(key, _)| key).collect();
+        assert_eq!(evicted.len(), 8);
+        assert_eq!(evicted[..], order[..8]);
+    }
+
+    #[test]
+    fn test_count_key_bytes() {
+        let long_key = "k".repeat(40);
+        let mut lfu = LFU::new().max_size(100).count_key_bytes(true);
+        lfu.insert(long_key.clone(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        assert_eq!(lfu.current_size(), 45);
+        lfu.insert(long_key.clone(), Bytes::from("4242"));
+        assert_eq!(lfu.current_size(), 47);
+        assert!(lfu.remove(&long_key));
+        assert_eq!(lfu.current_size(), 3);
+
+        // three long keys don't fit even though their values would
+        for i in 0..3 {
+            lfu.insert(format!("{}{}", long_key, i), Bytes::from("42"));
+        }
+        assert!(lfu.current_size() < 100);
+        assert!(lfu.len() < 4);
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_split_off_counts_key_bytes() {
+        let mut lfu = LFU::new().max_size(100).count_key_bytes(true);
+        lfu.insert("user:1".to_string(), Bytes::from("42"));
+        lfu.insert("post:1".to_string(), Bytes::from("43"));
+        let users = lfu.split_off(|key| key.starts_with("user:"));
+        assert_eq!(users.current_size(), 8);
+        assert_eq!(lfu.current_size(), 8);
+        assert_eq!(users.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_peak_levels() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        for _ in 0..20 {
+            lfu.get("a");
+        }
+        assert_eq!(lfu.frequency_levels(), 21);
+        assert_eq!(lfu.peak_levels(), 21);
+
+        lfu.remove("a");
+        lfu.compact();
+        assert_eq!(lfu.frequency_levels(), 1);
+        assert_eq!(lfu.peak_levels(), 21);
+
+        lfu.update_frequencies(|_, _| 30);
+        lfu.clear();
+        assert_eq!(lfu.frequency_levels(), 1);
+        assert_eq!(lfu.peak_levels(), 31);
+    }
+
+    #[test]
+    fn test_size_breakdown() {
+        let mut lfu = LFU::new().max_size(64);
+        assert_eq!(lfu.size_breakdown().value_bytes, 0);
+        for i in 0..40 {
+            lfu.insert(format!("key{}", i), Bytes::from(format!("value {}", i)));
+            lfu.get(&format!("key{}", i % 3));
+        }
+        let breakdown = lfu.size_breakdown();
+        assert_eq!(breakdown.value_bytes, lfu.current_size());
+        let key_bytes: usize = lfu.key_snapshot().iter().map(String::len).sum();
+        assert_eq!(breakdown.key_bytes, key_bytes * 2);
+        assert!(breakdown.frequency_list_bytes > 0);
+        assert!(breakdown.history_bytes > 0);
+    }
+
+    #[test]
+    fn test_append() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("log".to_string(), Bytes::from("a"));
+        lfu.get("log");
+        lfu.get("log");
+        assert!(lfu.append("log", b"bc"));
+        assert!(lfu.append("log", b"def"));
+        assert_eq!(lfu.peek("log"), Some(Bytes::from("abcdef")));
+        assert_eq!(lfu.get_frequency("log"), 2);
+        assert_eq!(lfu.current_size(), 6);
+        assert!(!lfu.append("missing", b"x"));
+        assert!(!lfu.contains_key("missing"));
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_append_limits_and_hooks() {
+        use std::sync::{Arc, Mutex};
+
+        let written = Arc::new(Mutex::new(vec![]));
+        let full = Arc::new(Mutex::new(0));
+        let (store, full_count) = (Arc::clone(&written), Arc::clone(&full));
+        let mut lfu = LFU::new()
+            .max_size(6)
+            .max_value_size(4)
+            .on_insert(move |key, value| {
+                store.lock().unwrap().push((key.to_string(), value.clone()))
+            })
+            .on_full(move || *full_count.lock().unwrap() += 1);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("4"));
+        assert!(!lfu.append("a", b"345"));
+        assert_eq!(lfu.peek("a"), Some(Bytes::from("42")));
+        assert_eq!(lfu.current_size(), 3);
+        assert_eq!(*full.lock().unwrap(), 0);
+
+        assert!(lfu.append("a", b"43"));
+        assert_eq!(lfu.peek("a"), Some(Bytes::from("4243")));
+        assert_eq!(*full.lock().unwrap(), 0);
+        assert!(lfu.append("b", b"3"));
+        assert_eq!(*full.lock().unwrap(), 1);
+        assert_eq!(
+            written.lock().unwrap().last(),
+            Some(&("b".to_string(), Bytes::from("43")))
+        );
+        assert_eq!(written.lock().unwrap().len(), 4);
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_clear_history() {
+        let mut lfu = LFU::new().max_size(5);
+        for key in &["a", "b", "c", "d"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+            lfu.get(key);
+        }
+        let cached = lfu.frequency_map();
+        let evicted: Vec<String> = lfu.eviction_history().map(|(key, _)| key.clone()).collect();
+        assert!(!evicted.is_empty());
+
+        lfu.clear_history();
+        for key in &evicted {
+            assert!(!lfu.has_evicted_recently(key));
+        }
+        assert_eq!(lfu.frequency_map(), cached);
+    }
+
+    #[test]
+    fn test_is_eviction_candidate() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert("hot".to_string(), Bytes::from("42"));
+        lfu.insert("cold".to_string(), Bytes::from("43"));
+        for _ in 0..3 {
+            lfu.get("hot");
+        }
+        lfu.get("cold");
+        assert!(lfu.is_eviction_candidate("cold"));
+        assert!(!lfu.is_eviction_candidate("hot"));
+        assert!(!lfu.is_eviction_candidate("missing"));
+
+        lfu.pin("cold");
+        assert!(!lfu.is_eviction_candidate("cold"));
+        assert!(lfu.is_eviction_candidate("hot"));
+    }
+
+    #[test]
+    fn test_contains_all_any() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        assert!(lfu.contains_all(&["a", "b"]));
+        assert!(lfu.contains_any(&["a", "b"]));
+        assert!(!lfu.contains_all(&["a", "c"]));
+        assert!(lfu.contains_any(&["c", "b"]));
+        assert!(!lfu.contains_all(&["c", "d"]));
+        assert!(!lfu.contains_any(&["c", "d"]));
+        assert!(lfu.contains_all(&[]));
+        assert!(!lfu.contains_any(&[]));
+    }
+
+    #[test]
+    fn test_populated_levels() {
+        let mut lfu = LFU::new().max_size(1024);
+        assert_eq!(lfu.populated_levels(), 0);
+        for (key, frequency) in &[("a", 0), ("b", 3), ("c", 3), ("d", 9)] {
+            lfu.insert_with_frequency(key.to_string(), Bytes::from("42"), *frequency);
+        }
+        assert_eq!(lfu.populated_levels(), 3);
+        assert_eq!(lfu.frequency_levels(), 10);
+
+        lfu.remove("d");
+        lfu.compact();
+        assert_eq!(lfu.populated_levels(), 2);
+        assert_eq!(lfu.frequency_levels(), 4);
+    }
+
+    #[test]
+    fn test_get_or_insert_with_status() {
+        let mut lfu = LFU::new();
+        let mut calls = 0;
+        let (value, inserted) = lfu
+            .get_or_insert_with_status("a".to_string(), || {
+                calls += 1;
+                Bytes::from("42")
+            })
+            .unwrap();
+        assert_eq!((value, inserted), (Bytes::from("42"), true));
+        let (value, inserted) = lfu
+            .get_or_insert_with_status("a".to_string(), || {
+                calls += 1;
+                Bytes::from("43")
+            })
+            .unwrap();
+        assert_eq!((value, inserted), (Bytes::from("42"), false));
+        assert_eq!(calls, 1);
+        assert_eq!(lfu.get_frequency("a"), 1);
+
+        let mut lfu = LFU::new().max_value_size(2);
+        assert_eq!(
+            lfu.get_or_insert_with_status("a".to_string(), || Bytes::from("424")),
+            Err(InsertError::ValueTooLarge { size: 3, limit: 2 })
+        );
+        assert!(lfu.is_empty());
+    }
+
+    #[test]
+    fn test_purge_older_than() {
+        let mut lfu = LFU::new().max_size(1024);
+        for key in &["a", "b", "c", "d"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        lfu.pin("d");
+        std::thread::sleep(Duration::from_millis(30));
+        lfu.get("b");
+        lfu.peek("c");
+        assert_eq!(lfu.purge_older_than(Duration::from_millis(20)), 2);
+        let mut keys = lfu.key_snapshot();
+        keys.sort();
+        assert_eq!(keys, vec!["b", "d"]);
+        assert!(lfu.has_evicted_recently("a"));
+        assert!(lfu.has_evicted_recently("c"));
+    }
+
+    #[test]
+    fn test_hottest_key() {
+        let mut lfu = LFU::new().max_size(1024);
+        for (key, reads) in &[("a", 2), ("b", 5), ("c", 1), ("d", 5)] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+            for _ in 0..*reads {
+                lfu.get(key);
+            }
+        }
+        assert_eq!(lfu.hottest_key(), Some((&"b".to_string(), 5)));
+        lfu.get("d");
+        assert_eq!(lfu.hottest_key(), Some((&"d".to_string(), 6)));
+        lfu.get("b");
+        assert_eq!(lfu.hottest_key(), Some((&"b".to_string(), 6)));
+    }
+
+    #[test]
+    fn test_insert_new() {
+        let mut lfu = LFU::new().case_insensitive(true);
+        assert_eq!(lfu.insert_new("a".to_string(), Bytes::from("42")), Ok(()));
+        lfu.get("a");
+        assert_eq!(
+            lfu.insert_new("A".to_string(), Bytes::from("43")),
+            Err(InsertError::KeyExists)
+        );
+        assert_eq!(lfu.peek("a"), Some(Bytes::from("42")));
+        assert_eq!(lfu.get_frequency("a"), 1);
+        assert_eq!(lfu.insert_new("b".to_string(), Bytes::from("44")), Ok(()));
+        assert_eq!(lfu.len(), 2);
+
+        let mut lfu = LFU::new().max_value_size(2);
+        assert_eq!(
+            lfu.insert_new("a".to_string(), Bytes::from("424")),
+            Err(InsertError::ValueTooLarge { size: 3, limit: 2 })
+        );
+        assert!(!lfu.contains_key("a"));
+    }
+
+    #[test]
+    fn test_keys_at_or_below() {
+        let mut lfu = LFU::new().max_size(1024);
+        for (i, frequency) in [0, 0, 1, 3, 3, 3, 7].iter().enumerate() {
+            lfu.insert_with_frequency(format!("k{}", i), Bytes::from("42"), *frequency);
+        }
+        assert_eq!(lfu.keys_at_or_below(0), 2);
+        assert_eq!(lfu.keys_at_or_below(2), 3);
+        assert_eq!(lfu.keys_at_or_below(3), 6);
+        assert_eq!(lfu.keys_at_or_below(usize::MAX), 7);
+    }
+
+    #[test]
+    fn test_drain_coldest() {
+        let mut lfu = LFU::new().max_size(1024);
+        for (key, reads) in &[("a", 3), ("b", 0), ("c", 1), ("d", 2)] {
+            lfu.insert(key.to_string(), Bytes::from(format!("{}{}", key, key)));
+            for _ in 0..*reads {
+                lfu.get(key);
+            }
+        }
+        let drained: Vec<(String, Bytes)> = lfu.drain_coldest().take(2).collect();
+        assert_eq!(
+            drained,
+            vec![
+                ("b".to_string(), Bytes::from("bb")),
+                ("c".to_string(), Bytes::from("cc"))
+            ]
+        );
+        let mut keys = lfu.key_snapshot();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "d"]);
+        assert_eq!(lfu.current_size(), 4);
+        assert!(!lfu.has_evicted_recently("b"));
+    }
+
+    #[test]
+    fn test_reserve_slot() {
+        let mut lfu = LFU::new().max_size(10);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.get("a");
+
+        assert!(lfu.reserve_slot("c".to_string(), 6));
+        assert!(!lfu.contains_key("b"));
+        assert_eq!(lfu.current_size(), 8);
+        assert!(!lfu.reserve_slot("c".to_string(), 1));
+        assert!(!lfu.reserve_slot("a".to_string(), 1));
+        assert_eq!(lfu.check_integrity(), Ok(()));
+
+        // reserved space isn't evictable by other inserts
+        lfu.insert("d".to_string(), Bytes::from("4"));
+        assert_eq!(lfu.reservations["c"], 6);
+
+        assert!(lfu.fulfill_slot("c", Bytes::from("4242")));
+        assert!(!lfu.fulfill_slot("c", Bytes::from("4242")));
+        assert_eq!(lfu.peek("c"), Some(Bytes::from("4242")));
+        assert_eq!(lfu.current_size(), 7);
+        assert_eq!(lfu.check_integrity(), Ok(()));
+
+        assert!(lfu.reserve_slot("e".to_string(), 2));
+        let size = lfu.current_size();
+        assert!(lfu.remove("e"));
+        assert_eq!(lfu.current_size(), size - 2);
+        assert!(!lfu.fulfill_slot("e", Bytes::from("42")));
+        assert!(!lfu.contains_key("e"));
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_fulfill_slot_refused_value() {
+        let mut lfu = LFU::new().max_size(10).max_value_size(2);
+        assert!(lfu.reserve_slot("a".to_string(), 2));
+        assert!(!lfu.fulfill_slot("a", Bytes::from("424")));
+        assert!(!lfu.contains_key("a"));
+        assert_eq!(lfu.current_size(), 2);
+        assert!(lfu.fulfill_slot("a", Bytes::from("42")));
+        assert_eq!(lfu.peek("a"), Some(Bytes::from("42")));
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut old = LFU::new().max_size(1024);
+        let mut new = LFU::new().max_size(1024);
+        for key in &["same", "bumped", "rewritten", "dropped", "dropped2"] {
+            old.insert(key.to_string(), Bytes::from("42"));
+        }
+        for key in &["same", "bumped", "rewritten", "added"] {
+            new.insert(key.to_string(), Bytes::from("42"));
+        }
+        new.get("bumped");
+        new.insert("rewritten".to_string(), Bytes::from("43"));
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.only_in_self, vec!["dropped", "dropped2"]);
+        assert_eq!(diff.only_in_other, vec!["added"]);
+        assert_eq!(diff.changed, vec!["bumped", "rewritten"]);
+        assert!(!diff.is_empty());
+
+        let reverse = new.diff(&old);
+        assert_eq!(reverse.only_in_self, vec!["added"]);
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn test_rw_lfu_concurrent_peeks() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let cache = Arc::new(RwLfu::new(LFU::new().max_size(256)));
+        for i in 0..16 {
+            cache.insert(format!("k{}", i), Bytes::from(format!("v{}", i)));
+        }
+        let readers: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    for round in 0..2000 {
+                        let i = round % 32;
+                        if let Some(value) = cache.peek(&format!("k{}", i)) {
+                            assert_eq!(value, Bytes::from(format!("v{}", i)));
+                        }
+                        assert!(cache.current_size() < 256);
+                    }
+                })
+            })
+            .collect();
+        let writer = {
+            let cache = Arc::clone(&cache);
+            thread::spawn(move || {
+                for round in 0..500 {
+                    let i = round % 32;
+                    cache.insert(format!("k{}", i), Bytes::from(format!("v{}", i)));
+                    cache.get(&format!("k{}", round % 5));
+                    if round % 7 == 0 {
+                        cache.remove(&format!("k{}", (round + 3) % 32));
+                    }
+                }
+            })
+        };
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        writer.join().unwrap();
+
+        let lfu = Arc::try_unwrap(cache).unwrap().into_inner();
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_keys_with_value() {
+        let mut lfu = LFU::new().max_size(1024);
+        let shared = Bytes::from("shared");
+        for key in &["c", "a", "e"] {
+            lfu.insert(key.to_string(), shared.clone());
+        }
+        lfu.insert("b".to_string(), Bytes::from("other"));
+        lfu.insert("d".to_string(), Bytes::from("shared!"));
+        assert_eq!(lfu.keys_with_value(&shared), vec!["a", "c", "e"]);
+        assert!(lfu.keys_with_value(&Bytes::from("none")).is_empty());
+    }
+
+    #[test]
+    fn test_unbounded() {
+        let mut lfu = LFU::new().unbounded();
+        for i in 0..1000 {
+            lfu.insert(format!("k{}", i), Bytes::from(format!("value {}", i)));
+        }
+        assert_eq!(lfu.len(), 1000);
+        assert_eq!(lfu.history_len(), 0);
+        assert!(lfu.current_size() > 64);
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_cold_count() {
+        let mut lfu = LFU::new().max_size(1024);
+        assert_eq!(lfu.cold_count(), 0);
+        for i in 0..10 {
+            lfu.insert(format!("k{}", i), Bytes::from("42"));
+        }
+        for i in 0..4 {
+            lfu.get(&format!("k{}", i));
+        }
+        lfu.peek("k9");
+        assert_eq!(lfu.cold_count(), 6);
+    }
+
+    #[test]
+    fn test_max_evictions_per_insert() {
+        let mut lfu = LFU::new().max_size(11).max_evictions_per_insert(1);
+        for i in 0..5 {
+            lfu.insert_with_frequency(format!("k{}", i), Bytes::from("42"), i);
+        }
+        for i in 0..4 {
+            let evicted = lfu
+                .insert_draining_evictions(format!("big{}", i), Bytes::from("42424242"))
+                .count();
+            assert_eq!(evicted, 1);
+        }
+        assert!(lfu.current_size() > 11);
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_effective_size() {
+        let value = Bytes::from("abcdef");
+        assert_eq!(LFU::new().effective_size("key", &value), 6);
+        assert_eq!(LFU::new().effective_size("key", &Bytes::new()), 1);
+
+        let mut lfu = LFU::new().max_size(1024).count_key_bytes(true);
+        assert_eq!(lfu.effective_size("key", &value), 9);
+        lfu.insert("key".to_string(), value.clone());
+        assert_eq!(lfu.current_size(), 9);
+
+        let mut lfu = LFU::new().max_size(1024).compression(RunLength);
+        let repetitive = Bytes::from(vec![b'a'; 100]);
+        assert_eq!(lfu.effective_size("key", &repetitive), 2);
+        assert_eq!(lfu.effective_size("key", &value), 12);
+        lfu.insert("key".to_string(), repetitive);
+        assert_eq!(lfu.current_size(), 2);
+    }
+
+    #[test]
+    fn test_eviction_filter() {
+        let mut lfu = LFU::new()
+            .max_size(7)
+            .eviction_filter(|key, _, _| key != "precious");
+        lfu.insert("other".to_string(), Bytes::from("42"));
+        lfu.insert("victim".to_string(), Bytes::from("43"));
+        lfu.insert("precious".to_string(), Bytes::from("44"));
+        // precious is the newest key in the coldest node, so it'd go first without the filter
+        assert_eq!(lfu.would_evict(2), vec!["victim".to_string()]);
+        lfu.insert("new".to_string(), Bytes::from("45"));
+        assert!(lfu.contains_key("precious"));
+        assert!(!lfu.contains_key("victim"));
+        assert!(lfu.has_evicted_recently("victim"));
+
+        // everything vetoed, insert goes over budget
+        let mut lfu = LFU::new().max_size(3).eviction_filter(|_, _, _| false);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        assert_eq!(lfu.len(), 2);
+        assert_eq!(lfu.current_size(), 4);
+    }
+
+    #[test]
+    fn test_frequencies_sorted() {
+        let mut lfu = LFU::new().max_size(1024);
+        let frequencies = [5, 0, 3, 3, 9, 0, 1, 3];
+        for (i, frequency) in frequencies.iter().enumerate() {
+            lfu.insert_with_frequency(format!("k{}", i), Bytes::from("42"), *frequency);
+        }
+        assert_eq!(lfu.frequencies_sorted(), vec![0, 0, 1, 3, 3, 3, 5, 9]);
+        let sorted = lfu.frequencies_sorted();
+        assert_eq!(sorted[sorted.len() / 2], 3);
+    }
+
+    #[test]
+    fn test_typed_lfu() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        let mut cache = TypedLfu::new(
+            LFU::new().max_size(1024),
+            |point: &Point| Bytes::from(format!("{},{}", point.x, point.y)),
+            |bytes: &Bytes| {
+                let text = std::str::from_utf8(bytes).ok()?;
+                let (x, y) = text.split_once(',')?;
+                Some(Point {
+                    x: x.parse().ok()?,
+                    y: y.parse().ok()?,
+                })
+            },
+        );
+        let point = Point { x: 3, y: -4 };
+        assert_eq!(cache.insert("p".to_string(), &point), None);
+        assert_eq!(cache.get("p"), Some(point.clone()));
+        assert_eq!(cache.inner_mut().get_frequency("p"), 1);
+        assert_eq!(
+            cache.insert("p".to_string(), &Point { x: 0, y: 0 }),
+            Some(point)
+        );
+
+        cache
+            .inner_mut()
+            .insert("garbage".to_string(), Bytes::from("nope"));
+        assert_eq!(cache.get("garbage"), None);
+        assert_eq!(cache.get("missing"), None);
+        assert_eq!(cache.inner().len(), 2);
+    }
+
+    #[test]
+    fn test_hot_byte_fraction() {
+        let mut lfu = LFU::new().max_size(1024);
+        lfu.insert_with_frequency("hot".to_string(), Bytes::from(vec![0; 30]), 5);
+        lfu.insert_with_frequency("warm".to_string(), Bytes::from(vec![0; 10]), 2);
+        lfu.insert_with_frequency("cold".to_string(), Bytes::from(vec![0; 60]), 0);
+        assert_eq!(lfu.hot_byte_fraction(0), 1.0);
+        assert_eq!(lfu.hot_byte_fraction(2), 0.4);
+        assert_eq!(lfu.hot_byte_fraction(3), 0.3);
+        assert_eq!(lfu.hot_byte_fraction(6), 0.0);
+    }
+
+    #[test]
+    fn test_stale_cold_keys() {
+        let mut lfu = LFU::new().max_size(1024);
+        for key in &["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        std::thread::sleep(Duration::from_millis(30));
+        lfu.get("b");
+        lfu.insert("fresh".to_string(), Bytes::from("43"));
+        assert_eq!(
+            lfu.stale_cold_keys(Duration::from_millis(20)),
+            vec!["a", "c"]
+        );
+        assert_eq!(
+            lfu.stale_cold_keys(Duration::from_millis(0)),
+            vec!["a", "c", "fresh"]
+        );
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let mut lfu = LFU::new().max_size(9);
+        for key in &["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+            lfu.get(key);
+        }
+        lfu.replace_all((0..6).map(|i| (format!("n{}", i), Bytes::from("43"))));
+        assert!(lfu.key_snapshot().iter().all(|key| key.starts_with('n')));
+        assert!(lfu.current_size() < 9);
+        assert_eq!(lfu.max_size, 9);
+        assert_eq!(lfu.frequency_map().values().max(), Some(&0));
+        assert_eq!(lfu.check_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn test_weighted_random_eviction() {
+        fn run(seed: u64) -> Vec<String> {
+            let mut lfu = LFU::new()
+                .max_size(41)
+                .history_capacity(1000)
+                .eviction_policy(EvictionPolicy::WeightedRandom)
+                .eviction_seed(seed);
+            for i in 0..300 {
+                lfu.insert(format!("k{}", i % 53), Bytes::from("42"));
+                lfu.get(&format!("k{}", i % 9));
+            }
+            lfu.eviction_history().map(|(key, _)| key.clone()).collect()
+        }
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+
+        let mut cold_evicted = 0;
+        for seed in 0..300 {
+            let mut lfu = LFU::new()
+                .max_size(5)
+                .eviction_policy(EvictionPolicy::WeightedRandom)
+                .eviction_seed(seed);
+            lfu.insert_with_frequency("hot".to_string(), Bytes::from("42"), 9);
+            lfu.insert("cold".to_string(), Bytes::from("43"));
+            let preview = lfu.would_evict(2);
+            lfu.insert("new".to_string(), Bytes::from("44"));
+            assert_eq!(preview.len(), 1);
+            assert!(!lfu.contains_key(&preview[0]));
+            if preview[0] == "cold" {
+                cold_evicted += 1;
+            }
+        }
+        // cold key weighs 1, hot key 0.1
+        assert!(cold_evicted > 240, "cold evicted {} times", cold_evicted);
+    }
+
+    #[test]
+    fn test_config() {
+        let lfu = LFU::new()
+            .max_size(4096)
+            .history_capacity(16)
+            .negative_cache(Duration::from_secs(5))
+            .max_frequency_cap(8)
+            .initial_frequency(1)
+            .count_key_bytes(true)
+            .eviction_policy(EvictionPolicy::WeightedRandom)
+            .on_full(|| {});
+        assert_eq!(
+            lfu.config(),
+            LfuConfig {
+                max_size: 4096,
+                history_capacity: 16,
+                negative_cache: Some(Duration::from_secs(5)),
+                max_frequency_cap: Some(8),
+                max_levels: None,
+                compression: false,
+                retain_evicted_data: 0,
+                case_insensitive: false,
+                preserve_frequency_on_overwrite: false,
+                initial_frequency: 1,
+                max_value_size: None,
+                count_key_bytes: true,
+                max_evictions_per_insert: None,
+                max_entries: None,
+                eviction_policy: EvictionPolicy::WeightedRandom,
+                on_insert: false,
+                on_full: true,
+                eviction_filter: false,
+            }
+        );
+        assert_eq!(LFU::new().config().eviction_policy, EvictionPolicy::Lfu);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_async_lfu_flood_settles_within_budget() {
+        let cache = AsyncLfu::new(LFU::new().max_size(512), 16);
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let cache = cache.clone();
+                tokio::spawn(async move {
+                    for i in 0..1000 {
+                        cache
+                            .insert(
+                                format!("p{}k{}", p, i % 100),
+                                Bytes::from(vec![p as u8; 10]),
+                            )
+                            .await;
+                        cache.get(&format!("p{}k{}", p, i % 3));
+                    }
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.await.unwrap();
+        }
+        cache.flush().await;
+        assert!(cache.current_size() < 512);
+        assert!(!cache.is_empty());
+        assert_eq!(cache.lock().check_integrity(), Ok(()));
+
+        cache.insert("last".to_string(), Bytes::from("v")).await;
+        cache.flush().await;
+        assert_eq!(cache.peek("last"), Some(Bytes::from("v")));
+    }
+
+    #[test]
+    fn test_headroom() {
+        // bytes bind: two large values fill most of max_size while entries are plentiful
+        let mut lfu = LFU::new().max_size(20).max_entries(10);
+        lfu.insert("a".to_string(), Bytes::from(vec![0; 8]));
+        lfu.insert("b".to_string(), Bytes::from(vec![0; 8]));
+        assert_eq!(lfu.headroom(), (4, 8));
+        lfu.insert("c".to_string(), Bytes::from(vec![0; 8]));
+        assert_eq!(lfu.len(), 2);
+        assert_eq!(lfu.headroom(), (4, 8));
+
+        // entries bind: small values hit the entry limit long before max_size
+        let mut lfu = LFU::new().max_size(1000).max_entries(3);
+        for key in ["a", "b", "c"].iter() {
+            lfu.insert(key.to_string(), Bytes::from("xy"));
+        }
+        assert_eq!(lfu.headroom(), (994, 0));
+        lfu.get("a");
+        lfu.insert("d".to_string(), Bytes::from("xy"));
+        assert_eq!(lfu.len(), 3);
+        assert!(lfu.contains_key("a"));
+        assert!(lfu.contains_key("d"));
+        assert_eq!(lfu.headroom(), (994, 0));
+
+        // overwriting an existing key doesn't need an extra entry
+        lfu.insert("d".to_string(), Bytes::from("xyz"));
+        assert_eq!(lfu.len(), 3);
+        assert_eq!(lfu.headroom(), (993, 0));
+
+        // shrinking max_size below current_size saturates instead of underflowing
+        lfu.set_max_size(4);
+        assert_eq!(lfu.headroom(), (0, 0));
+        assert_eq!(LFU::new().max_size(5).headroom(), (5, usize::MAX));
+    }
+
+    #[test]
+    fn test_max_entries_default_and_setter() {
+        let mut lfu = LFU::default().max_size(64);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        assert_eq!(lfu.len(), 2);
+        assert_eq!(lfu.config().max_entries, None);
+
+        lfu.set_max_entries(2);
+        assert_eq!(lfu.headroom(), (60, 0));
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        assert_eq!(lfu.len(), 2);
+        assert_eq!(lfu.config().max_entries, Some(2));
+    }
+
+    #[test]
+    fn test_readmit_respects_max_entries() {
+        let mut lfu = LFU::new().max_size(5).max_entries(2).retain_evicted_data(8);
+        for key in ["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        assert_eq!(lfu.len(), 2);
+        // size budget no longer binds, entry limit still does
+        assert_eq!(lfu.set_max_size_and_readmit(1024), 0);
+        assert_eq!(lfu.len(), 2);
+        lfu.set_max_entries(3);
+        assert_eq!(lfu.set_max_size_and_readmit(2048), 1);
+        assert_eq!(lfu.len(), 3);
+        assert_eq!(lfu.check_integrity(), Ok(()));
     }
 }
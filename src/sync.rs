@@ -0,0 +1,118 @@
+//! Thread safe wrapper over `LFU` for read heavy workloads
+
+use crate::LFU;
+use bytes::Bytes;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// `LFU` behind a `RwLock`. `peek`, `contains_key`, `current_size` and `len` share a read lock so
+/// they run concurrently, while `insert`, `get` and `remove` take the write lock.
+///
+/// `get` needs the write lock because it bumps frequency, which moves the key between frequency
+/// nodes. Use `peek` when the read shouldn't count towards frequency and should not block other readers.
+///
+/// Values are returned as owned `Bytes`, which clone cheaply, so no lock is held after a call returns.
+///
+/// ```
+/// use lfu_vecs::{RwLfu, LFU};
+/// use bytes::Bytes;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let cache = Arc::new(RwLfu::new(LFU::new().max_size(1024)));
+/// cache.insert("a".to_string(), Bytes::from("b"));
+/// let reader = Arc::clone(&cache);
+/// let peeked = thread::spawn(move || reader.peek("a")).join().unwrap();
+/// assert_eq!(peeked, Some(Bytes::from("b")));
+/// ```
+#[derive(Debug, Default)]
+pub struct RwLfu {
+    inner: RwLock<LFU>,
+}
+
+impl RwLfu {
+    pub fn new(lfu: LFU) -> Self {
+        RwLfu {
+            inner: RwLock::new(lfu),
+        }
+    }
+    ///
+    /// Read a value without bumping its frequency, under a read lock
+    ///
+    pub fn peek(&self, key: &str) -> Option<Bytes> {
+        self.read().peek(key)
+    }
+    ///
+    /// Check if we have value for this key, under a read lock
+    ///
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.read().contains_key(key)
+    }
+    ///
+    /// Current size of cached data, under a read lock
+    ///
+    pub fn current_size(&self) -> usize {
+        self.read().current_size()
+    }
+    ///
+    /// Number of entries in cache, under a read lock
+    ///
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+    ///
+    /// Check if there are no entries in cache, under a read lock
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+    ///
+    /// Get a value bumping its frequency. Takes the write lock, see `peek` for a read lock alternative.
+    ///
+    pub fn get(&self, key: &str) -> Option<Bytes> {
+        self.write().get(key)
+    }
+    ///
+    /// Insert a value returning the previous one, under the write lock
+    ///
+    pub fn insert(&self, key: String, value: Bytes) -> Option<Bytes> {
+        self.write().insert(key, value)
+    }
+    ///
+    /// Remove a key returning whether it was cached, under the write lock
+    ///
+    pub fn remove(&self, key: &str) -> bool {
+        self.write().remove(key)
+    }
+    ///
+    /// Read lock over the whole cache for operations not mirrored by the wrapper
+    ///
+    /// # Panics
+    ///
+    /// When another thread panicked while holding the write lock.
+    ///
+    pub fn read(&self) -> RwLockReadGuard<'_, LFU> {
+        self.inner.read().expect("RwLfu lock poisoned")
+    }
+    ///
+    /// Write lock over the whole cache for operations not mirrored by the wrapper
+    ///
+    /// # Panics
+    ///
+    /// When another thread panicked while holding the write lock.
+    ///
+    pub fn write(&self) -> RwLockWriteGuard<'_, LFU> {
+        self.inner.write().expect("RwLfu lock poisoned")
+    }
+    ///
+    /// Unwrap the cache
+    ///
+    pub fn into_inner(self) -> LFU {
+        self.inner.into_inner().expect("RwLfu lock poisoned")
+    }
+}
+
+impl From<LFU> for RwLfu {
+    fn from(lfu: LFU) -> Self {
+        RwLfu::new(lfu)
+    }
+}
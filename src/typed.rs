@@ -0,0 +1,86 @@
+//! Typed values on top of the byte oriented `LFU`
+
+use crate::{Hook, LFU};
+use bytes::Bytes;
+use std::fmt;
+
+type Serialize<T> = Hook<dyn Fn(&T) -> Bytes + Send + Sync>;
+type Deserialize<T> = Hook<dyn Fn(&Bytes) -> Option<T> + Send + Sync>;
+
+/// `LFU` storing values of type `T`, converted to and from bytes by the provided functions.
+/// Sizing, eviction and frequencies all work on the serialized form.
+///
+/// ```
+/// use lfu_vecs::{TypedLfu, LFU};
+/// use bytes::Bytes;
+///
+/// let mut cache = TypedLfu::new(
+///     LFU::new(),
+///     |value: &u32| Bytes::from(value.to_be_bytes().to_vec()),
+///     |bytes: &Bytes| Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+/// );
+/// cache.insert("a".to_string(), &42);
+/// assert_eq!(cache.get("a"), Some(42));
+/// ```
+pub struct TypedLfu<T> {
+    lfu: LFU,
+    serialize: Serialize<T>,
+    deserialize: Deserialize<T>,
+}
+
+impl<T> TypedLfu<T> {
+    pub fn new(
+        lfu: LFU,
+        serialize: impl Fn(&T) -> Bytes + Send + Sync + 'static,
+        deserialize: impl Fn(&Bytes) -> Option<T> + Send + Sync + 'static,
+    ) -> Self {
+        TypedLfu {
+            lfu,
+            serialize: Hook(Box::new(serialize)),
+            deserialize: Hook(Box::new(deserialize)),
+        }
+    }
+    ///
+    /// Serialize and insert a value, returns the previous one if it deserializes
+    ///
+    pub fn insert(&mut self, key: String, value: &T) -> Option<T> {
+        let previous = self.lfu.insert(key, (self.serialize.0)(value))?;
+        (self.deserialize.0)(&previous)
+    }
+    ///
+    /// Get a value bumping its frequency like `LFU::get`. None when the key is missing or its
+    /// value doesn't deserialize.
+    ///
+    pub fn get(&mut self, key: &str) -> Option<T> {
+        let value = self.lfu.get(key)?;
+        (self.deserialize.0)(&value)
+    }
+    ///
+    /// Underlying cache, for operations that don't involve values
+    ///
+    pub fn inner(&self) -> &LFU {
+        &self.lfu
+    }
+    ///
+    /// Mutable access to underlying cache, values inserted through it have to be in serialized form
+    ///
+    pub fn inner_mut(&mut self) -> &mut LFU {
+        &mut self.lfu
+    }
+    ///
+    /// Unwrap the cache
+    ///
+    pub fn into_inner(self) -> LFU {
+        self.lfu
+    }
+}
+
+impl<T> fmt::Debug for TypedLfu<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedLfu")
+            .field("lfu", &self.lfu)
+            .field("serialize", &self.serialize)
+            .field("deserialize", &self.deserialize)
+            .finish()
+    }
+}
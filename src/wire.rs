@@ -0,0 +1,216 @@
+//! Compact binary format for sharing a warm cache between processes, see `LFU::encode`
+//!
+//! Layout, integers big endian:
+//!
+//! ```text
+//! magic "LFUV" | version u8 | max_size u64 | max_entries u64 | count u64
+//! count times: key_len u32 | key | frequency u64 | flags u8 | value_len u32 | value
+//! ```
+//!
+//! Values are written decompressed, flags bit 0 marks pinned entries. max_entries is u64::MAX
+//! when there's no entry limit. Frequencies above
+//! `MAX_FREQUENCY` are refused on decode since each level is backed by a frequency node.
+
+use crate::{Item, LFU};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::convert::TryFrom;
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"LFUV";
+const VERSION: u8 = 2;
+const PINNED: u8 = 1;
+const NO_LIMIT: u64 = u64::MAX;
+/// highest frequency `decode` accepts, keeps a corrupt frame from allocating frequency nodes without bound
+const MAX_FREQUENCY: usize = 1 << 20;
+
+/// Reason `LFU::encode` couldn't write a cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    /// key or value of an entry is longer than its u32 length prefix can express
+    TooLong { key: String },
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::TooLong { key } => {
+                write!(f, "entry {:?} is too long for a u32 length prefix", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+/// Reason `LFU::decode` couldn't read a cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// buffer doesn't start with the format magic
+    BadMagic,
+    /// buffer was written by an unknown format version
+    UnsupportedVersion(u8),
+    /// buffer ends in the middle of a frame
+    UnexpectedEof,
+    /// key isn't valid UTF-8
+    InvalidKey,
+    /// same key is encoded twice
+    DuplicateKey(String),
+    /// encoded number doesn't fit into usize on this platform
+    Overflow,
+    /// entry frequency is above the highest one decode accepts
+    FrequencyTooHigh(usize),
+    /// frame holds more entries than its own max_entries allows
+    TooManyEntries { count: usize, limit: usize },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "not an encoded cache, magic doesn't match"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            DecodeError::UnexpectedEof => write!(f, "buffer ends in the middle of a frame"),
+            DecodeError::InvalidKey => write!(f, "key isn't valid UTF-8"),
+            DecodeError::DuplicateKey(key) => write!(f, "key {:?} is encoded twice", key),
+            DecodeError::Overflow => write!(f, "encoded number doesn't fit into usize"),
+            DecodeError::FrequencyTooHigh(frequency) => {
+                write!(
+                    f,
+                    "frequency {} exceeds the highest accepted {}",
+                    frequency, MAX_FREQUENCY
+                )
+            }
+            DecodeError::TooManyEntries { count, limit } => {
+                write!(f, "{} entries exceed max_entries of {}", count, limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl LFU {
+    ///
+    /// Encode entries, their frequencies, pins, max_size and max_entries into a self describing binary frame
+    /// readable with `LFU::decode`. Entries are written in key order. Fails when a key or value
+    /// doesn't fit a u32 length prefix.
+    ///
+    /// ```
+    /// use lfu_vecs::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// let mut buf = lfu.encode().unwrap();
+    /// let mut copy = LFU::decode(&mut buf).unwrap();
+    /// assert_eq!(copy.get_frequency("a"), 1);
+    /// ```
+    pub fn encode(&self) -> Result<Bytes, EncodeError> {
+        let mut keys: Vec<&String> = self.items.keys().collect();
+        keys.sort_unstable();
+        let mut buf = BytesMut::new();
+        buf.put_slice(MAGIC);
+        buf.put_u8(VERSION);
+        buf.put_u64(self.max_size as u64);
+        buf.put_u64(self.max_entries.map_or(NO_LIMIT, |limit| limit as u64));
+        buf.put_u64(keys.len() as u64);
+        for key in keys {
+            let item = &self.items[key];
+            let value = self.decompress(item.data.clone());
+            let too_long = || EncodeError::TooLong { key: key.clone() };
+            buf.put_u32(u32::try_from(key.len()).map_err(|_| too_long())?);
+            buf.put_slice(key.as_bytes());
+            buf.put_u64(item.parent as u64);
+            buf.put_u8(if item.pinned { PINNED } else { 0 });
+            buf.put_u32(u32::try_from(value.len()).map_err(|_| too_long())?);
+            buf.put_slice(&value);
+        }
+        Ok(buf.freeze())
+    }
+    ///
+    /// Read a cache written by `encode`, advancing `buf` past the frame so several frames can be
+    /// read back to back. On error, including frequencies above 2^20, `buf` is left untouched. Entries are restored as they were,
+    /// without evicting, and other settings start at their defaults.
+    ///
+    /// ```
+    /// use lfu_vecs::{DecodeError, LFU};
+    /// use bytes::Bytes;
+    /// let mut buf = Bytes::from("nope");
+    /// assert_eq!(LFU::decode(&mut buf).unwrap_err(), DecodeError::BadMagic);
+    /// ```
+    pub fn decode(buf: &mut Bytes) -> Result<LFU, DecodeError> {
+        let mut frame = buf.clone();
+        if take(&mut frame, MAGIC.len())? != MAGIC[..] {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = take(&mut frame, 1)?[0];
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let mut lfu = LFU::new().max_size(read_u64(&mut frame)?);
+        lfu.max_entries = read_limit(&mut frame)?;
+        let count = read_u64(&mut frame)?;
+        if let Some(limit) = lfu.max_entries.filter(|&limit| count > limit) {
+            return Err(DecodeError::TooManyEntries { count, limit });
+        }
+        for _ in 0..count {
+            let key_len = read_u32(&mut frame)?;
+            let key = String::from_utf8(take(&mut frame, key_len)?.to_vec())
+                .map_err(|_| DecodeError::InvalidKey)?;
+            let frequency = read_u64(&mut frame)?;
+            if frequency > MAX_FREQUENCY {
+                return Err(DecodeError::FrequencyTooHigh(frequency));
+            }
+            let flags = take(&mut frame, 1)?[0];
+            let value_len = read_u32(&mut frame)?;
+            let value = take(&mut frame, value_len)?;
+            if lfu.items.contains_key(&key) {
+                return Err(DecodeError::DuplicateKey(key));
+            }
+            let mut item = Item::new(value, lfu.charge(value_len));
+            item.parent = frequency;
+            item.pinned = flags & PINNED != 0;
+            item.seq = lfu.next_seq;
+            lfu.next_seq += 1;
+            lfu.attach_item(key, item);
+        }
+        *buf = frame;
+        Ok(lfu)
+    }
+}
+
+/// Split off next `n` bytes of a frame
+fn take(frame: &mut Bytes, n: usize) -> Result<Bytes, DecodeError> {
+    if frame.remaining() < n {
+        return Err(DecodeError::UnexpectedEof);
+    }
+    Ok(frame.split_to(n))
+}
+
+fn read_u32(frame: &mut Bytes) -> Result<usize, DecodeError> {
+    if frame.remaining() < 4 {
+        return Err(DecodeError::UnexpectedEof);
+    }
+    usize::try_from(frame.get_u32()).map_err(|_| DecodeError::Overflow)
+}
+
+fn read_u64(frame: &mut Bytes) -> Result<usize, DecodeError> {
+    if frame.remaining() < 8 {
+        return Err(DecodeError::UnexpectedEof);
+    }
+    usize::try_from(frame.get_u64()).map_err(|_| DecodeError::Overflow)
+}
+
+/// Optional limit, `NO_LIMIT` stands for None
+fn read_limit(frame: &mut Bytes) -> Result<Option<usize>, DecodeError> {
+    if frame.remaining() < 8 {
+        return Err(DecodeError::UnexpectedEof);
+    }
+    match frame.get_u64() {
+        NO_LIMIT => Ok(None),
+        limit => usize::try_from(limit)
+            .map(Some)
+            .map_err(|_| DecodeError::Overflow),
+    }
+}